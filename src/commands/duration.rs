@@ -0,0 +1,362 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use chrono::{DateTime, Months, TimeDelta, Utc};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeUnits {
+    Minutes,
+    Hours,
+    Days,
+    Weeks,
+    Months
+}
+
+impl TimeUnits {
+    pub fn apply_delta(&self, time: DateTime<Utc>, amount: u32) -> anyhow::Result<DateTime<Utc>> {
+        let res = match &self {
+            TimeUnits::Minutes => time.checked_add_signed(TimeDelta::minutes(amount as i64)),
+            TimeUnits::Hours => time.checked_add_signed(TimeDelta::hours(amount as i64)),
+            TimeUnits::Days => time.checked_add_signed(TimeDelta::days(amount as i64)),
+            TimeUnits::Weeks => time.checked_add_signed(TimeDelta::weeks(amount as i64)),
+            TimeUnits::Months => time.checked_add_months(Months::new(amount))
+        };
+
+        res.ok_or(anyhow!("Time out of maximum range"))
+    }
+
+    pub fn remove_delta(&self, time: DateTime<Utc>, amount: u32) -> anyhow::Result<DateTime<Utc>> {
+        let res = match &self {
+            TimeUnits::Minutes => time.checked_sub_signed(TimeDelta::minutes(amount as i64)),
+            TimeUnits::Hours => time.checked_sub_signed(TimeDelta::hours(amount as i64)),
+            TimeUnits::Days => time.checked_sub_signed(TimeDelta::days(amount as i64)),
+            TimeUnits::Weeks => time.checked_sub_signed(TimeDelta::weeks(amount as i64)),
+            TimeUnits::Months => time.checked_sub_months(Months::new(amount))
+        };
+
+        res.ok_or(anyhow!("Time out of maximum range"))
+    }
+
+    fn from_suffix(suffix: &str) -> Option<TimeUnits> {
+        match suffix {
+            "m" | "min" | "mins" | "minute" | "minutes" => Some(TimeUnits::Minutes),
+            "h" | "hr" | "hrs" | "hour" | "hours" => Some(TimeUnits::Hours),
+            "d" | "day" | "days" => Some(TimeUnits::Days),
+            "w" | "wk" | "wks" | "week" | "weeks" => Some(TimeUnits::Weeks),
+            "mo" | "mon" | "month" | "months" => Some(TimeUnits::Months),
+            _ => None
+        }
+    }
+
+    fn name(&self, amount: u32) -> &'static str {
+        let (singular, plural) = match self {
+            TimeUnits::Minutes => ("minute", "minutes"),
+            TimeUnits::Hours => ("hour", "hours"),
+            TimeUnits::Days => ("day", "days"),
+            TimeUnits::Weeks => ("week", "weeks"),
+            TimeUnits::Months => ("month", "months")
+        };
+
+        if amount == 1 { singular } else { plural }
+    }
+}
+
+/// Why a time argument couldn't be understood, shown to the user as-is
+#[derive(Debug)]
+pub struct ParseTimeError(String);
+
+impl fmt::Display for ParseTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseTimeError {}
+
+/// A length of time given as a command argument, like "1d12h", "90m" or "2 weeks and 3 days"
+#[derive(Clone, Debug)]
+pub struct Duration(Vec<(u32, TimeUnits)>);
+
+impl Duration {
+    pub fn of(amount: u32, time_units: TimeUnits) -> Duration {
+        Duration(vec![(amount, time_units)])
+    }
+
+    /// The time this long after `time`
+    pub fn after(&self, time: DateTime<Utc>) -> anyhow::Result<DateTime<Utc>> {
+        self.0.iter().try_fold(time, |time, (amount, time_units)| time_units.apply_delta(time, *amount))
+    }
+
+    /// The time this long before `time`
+    pub fn before(&self, time: DateTime<Utc>) -> anyhow::Result<DateTime<Utc>> {
+        self.0.iter().try_fold(time, |time, (amount, time_units)| time_units.remove_delta(time, *amount))
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.0.iter()
+            .map(|(amount, time_units)| format!("{} {}", amount, time_units.name(*amount)))
+            .collect();
+        f.write_str(&parts.join(" "))
+    }
+}
+
+impl FromStr for Duration {
+    type Err = ParseTimeError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let lowercase = input.trim().to_lowercase();
+        let mut chars = lowercase.strip_prefix("in ").unwrap_or(&lowercase).chars().peekable();
+        let mut parts = vec![];
+
+        loop {
+            while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+            if chars.peek().is_none() {
+                break;
+            }
+
+            let mut number = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                number.push(c);
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            let mut suffix = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_alphabetic()) {
+                suffix.push(c);
+            }
+
+            if number.is_empty() {
+                if suffix == "and" {
+                    continue;
+                }
+                return Err(ParseTimeError(format!("Couldn't understand \"{}\", use a duration like 30m, 1d12h or 2 weeks", input.trim())));
+            }
+            let amount = number.parse::<u32>()
+                .map_err(|_| ParseTimeError(format!("{} is too large", number)))?;
+            if suffix.is_empty() {
+                return Err(ParseTimeError(format!("\"{}\" needs a unit, like {}m, {}h or {}d", number, number, number, number)));
+            }
+            let time_units = TimeUnits::from_suffix(&suffix)
+                .ok_or(ParseTimeError(format!("Unknown time unit \"{}\", use m, h, d, w or mo", suffix)))?;
+            parts.push((amount, time_units));
+        }
+
+        if parts.iter().all(|(amount, _)| *amount == 0) {
+            return Err(ParseTimeError("Duration has to be longer than 0".to_string()));
+        }
+
+        Ok(Duration(parts))
+    }
+}
+
+/// A point in time given as a command argument, either a duration from now or an absolute date
+#[derive(Clone, Debug)]
+pub enum When {
+    In(Duration),
+    At(DateTime<Utc>)
+}
+
+impl When {
+    pub fn resolve(&self, now: DateTime<Utc>) -> anyhow::Result<DateTime<Utc>> {
+        match self {
+            When::In(duration) => duration.after(now),
+            When::At(time) => Ok(*time)
+        }
+    }
+}
+
+impl FromStr for When {
+    type Err = ParseTimeError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let duration_error = match input.parse::<Duration>() {
+            Ok(duration) => return Ok(When::In(duration)),
+            Err(e) => e
+        };
+        // A short bare number is a duration missing its unit, not a unix timestamp from 1970
+        if input.trim().len() < 9 && input.trim().chars().all(|c| c.is_ascii_digit()) {
+            return Err(duration_error);
+        }
+
+        match parse_absolute_time(input) {
+            Some(time) => Ok(When::At(time)),
+            // Only talk about dates if it looks like one was meant
+            None if input.contains(['-', ':', '<']) => Err(ParseTimeError(format!(
+                "Couldn't understand \"{}\", use a Discord timestamp or a UTC date like 2025-01-31 18:00", input.trim()))),
+            None => Err(duration_error)
+        }
+    }
+}
+
+/// Parse an absolute time, either as a Discord timestamp, a unix timestamp or a UTC date
+fn parse_absolute_time(input: &str) -> Option<DateTime<Utc>> {
+    let input = input.trim();
+
+    let unix_timestamp = input.strip_prefix("<t:")
+        .and_then(|rest| rest.strip_suffix('>'))
+        .map(|rest| rest.split(':').next().unwrap_or(rest))
+        .unwrap_or(input);
+    if let Ok(seconds) = unix_timestamp.parse::<i64>() {
+        return DateTime::from_timestamp(seconds, 0);
+    }
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Some(time.to_utc());
+    }
+    if let Ok(time) = chrono::NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M") {
+        return Some(time.and_utc());
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Some(date.and_time(chrono::NaiveTime::MIN).and_utc());
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        let cases: &[(&str, &[(u32, TimeUnits)])] = &[
+            ("30m", &[(30, TimeUnits::Minutes)]),
+            ("90 mins", &[(90, TimeUnits::Minutes)]),
+            ("1d12h", &[(1, TimeUnits::Days), (12, TimeUnits::Hours)]),
+            ("2 weeks and 3 days", &[(2, TimeUnits::Weeks), (3, TimeUnits::Days)]),
+            ("1h, 30m", &[(1, TimeUnits::Hours), (30, TimeUnits::Minutes)]),
+            ("1h and", &[(1, TimeUnits::Hours)]),
+            ("1 hour, 2 minutes and 3 days", &[(1, TimeUnits::Hours), (2, TimeUnits::Minutes), (3, TimeUnits::Days)]),
+            ("in 5 minutes", &[(5, TimeUnits::Minutes)]),
+            ("  6MO ", &[(6, TimeUnits::Months)]),
+            ("1w 1mon", &[(1, TimeUnits::Weeks), (1, TimeUnits::Months)]),
+            ("0h 30m", &[(0, TimeUnits::Hours), (30, TimeUnits::Minutes)]),
+            ("4294967295mo", &[(u32::MAX, TimeUnits::Months)]),
+        ];
+
+        for (input, expected) in cases {
+            let duration = input.parse::<Duration>().unwrap_or_else(|e| panic!("{:?} was rejected: {}", input, e));
+            assert_eq!(duration.0, *expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        let cases = [
+            ("", "Duration has to be longer than 0"),
+            ("0m", "Duration has to be longer than 0"),
+            ("0d and 0h", "Duration has to be longer than 0"),
+            ("soon", "Couldn't understand \"soon\", use a duration like 30m, 1d12h or 2 weeks"),
+            ("-5m", "Couldn't understand \"-5m\", use a duration like 30m, 1d12h or 2 weeks"),
+            ("30", "\"30\" needs a unit, like 30m, 30h or 30d"),
+            ("1h 30", "\"30\" needs a unit, like 30m, 30h or 30d"),
+            ("5 fortnights", "Unknown time unit \"fortnights\", use m, h, d, w or mo"),
+            ("4294967296mo", "4294967296 is too large"),
+        ];
+
+        for (input, expected) in cases {
+            match input.parse::<Duration>() {
+                Ok(duration) => panic!("{:?} was accepted as {:?}", input, duration),
+                Err(e) => assert_eq!(e.to_string(), expected, "{:?}", input)
+            }
+        }
+    }
+
+    #[test]
+    fn applies_durations() {
+        let start = Utc.with_ymd_and_hms(2025, 1, 31, 18, 0, 0).unwrap();
+        let cases = [
+            ("1d12h", Utc.with_ymd_and_hms(2025, 2, 2, 6, 0, 0).unwrap()),
+            ("1mo", Utc.with_ymd_and_hms(2025, 2, 28, 18, 0, 0).unwrap()),
+            ("2w 30m", Utc.with_ymd_and_hms(2025, 2, 14, 18, 30, 0).unwrap()),
+        ];
+
+        for (input, expected) in cases {
+            let duration = input.parse::<Duration>().unwrap();
+            assert_eq!(duration.after(start).unwrap(), expected, "{:?}", input);
+        }
+
+        // Parts are applied in order, a month back from March 1st is February 1st
+        let duration = "1mo 1d".parse::<Duration>().unwrap();
+        assert_eq!(duration.before(Utc.with_ymd_and_hms(2025, 3, 1, 18, 0, 0).unwrap()).unwrap(),
+            Utc.with_ymd_and_hms(2025, 1, 31, 18, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn rejects_overflowing_durations() {
+        let now = Utc::now();
+        for input in ["4294967295mo", "4294967295w", "4294967295d"] {
+            let duration = input.parse::<Duration>().unwrap();
+            assert_eq!(duration.after(now).unwrap_err().to_string(), "Time out of maximum range", "{:?}", input);
+            assert_eq!(duration.before(now).unwrap_err().to_string(), "Time out of maximum range", "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn formats_durations() {
+        let cases = [
+            ("1d12h", "1 day 12 hours"),
+            ("1m", "1 minute"),
+            ("2 weeks and 1 month", "2 weeks 1 month"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<Duration>().unwrap().to_string(), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn parses_times() {
+        let timestamp = Utc.with_ymd_and_hms(2023, 11, 14, 22, 13, 20).unwrap();
+        let cases = [
+            ("1700000000", timestamp),
+            ("<t:1700000000>", timestamp),
+            ("<t:1700000000:R>", timestamp),
+            ("2023-11-14T22:13:20Z", timestamp),
+            ("2023-11-14T23:13:20+01:00", timestamp),
+            ("2025-01-31 18:00", Utc.with_ymd_and_hms(2025, 1, 31, 18, 0, 0).unwrap()),
+            ("2025-01-31", Utc.with_ymd_and_hms(2025, 1, 31, 0, 0, 0).unwrap()),
+        ];
+
+        for (input, expected) in cases {
+            match input.parse::<When>() {
+                Ok(When::At(time)) => assert_eq!(time, expected, "{:?}", input),
+                other => panic!("{:?} was parsed as {:?}", input, other)
+            }
+        }
+
+        // Durations are counted from the time they're resolved at
+        let now = Utc.with_ymd_and_hms(2025, 1, 31, 18, 0, 0).unwrap();
+        match "in 1 day".parse::<When>() {
+            Ok(when @ When::In(_)) => assert_eq!(when.resolve(now).unwrap(), Utc.with_ymd_and_hms(2025, 2, 1, 18, 0, 0).unwrap()),
+            other => panic!("\"in 1 day\" was parsed as {:?}", other)
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        let cases = [
+            // Numbers shorter than 9 digits are durations missing a unit, not timestamps from 1970
+            ("12345678", "\"12345678\" needs a unit, like 12345678m, 12345678h or 12345678d"),
+            ("30", "\"30\" needs a unit, like 30m, 30h or 30d"),
+            ("tomorrow", "Couldn't understand \"tomorrow\", use a duration like 30m, 1d12h or 2 weeks"),
+            ("2025-13-45", "Couldn't understand \"2025-13-45\", use a Discord timestamp or a UTC date like 2025-01-31 18:00"),
+            ("<t:soon>", "Couldn't understand \"<t:soon>\", use a Discord timestamp or a UTC date like 2025-01-31 18:00"),
+            ("18:00", "Couldn't understand \"18:00\", use a Discord timestamp or a UTC date like 2025-01-31 18:00"),
+        ];
+
+        for (input, expected) in cases {
+            match input.parse::<When>() {
+                Ok(when) => panic!("{:?} was accepted as {:?}", input, when),
+                Err(e) => assert_eq!(e.to_string(), expected, "{:?}", input)
+            }
+        }
+
+        // 9 digits or more is a unix timestamp
+        assert!(matches!("123456789".parse::<When>(), Ok(When::At(_))));
+    }
+}
@@ -1,8 +1,16 @@
 mod moderation;
 mod development;
+mod duration;
+mod reminders;
+mod role_menus;
 
 use poise::serenity_prelude::*;
 use crate::context::{Context, Data};
+use crate::events::router::Route;
+
+pub use reminders::deliver_due as deliver_due_reminders;
+pub use moderation::{check_can_moderate, end_ban_records, end_dunce_records, join_within, linked_accounts, execute_dunce, plan_dunce, reconcile_dunces_on_startup, revert_expired_slowmodes, send_mod_action_log, summarize_notes, swap_roles, user_notes, warn_user, DunceExpiry};
+pub use duration::{Duration, TimeUnits, When};
 
 pub async fn error_handler(error: poise::FrameworkError<'_, Data, anyhow::Error>) {
     if let poise::FrameworkError::Command {error, ctx, .. } = error {
@@ -10,6 +18,12 @@ pub async fn error_handler(error: poise::FrameworkError<'_, Data, anyhow::Error>
             eprintln!("Error in error handling: {:?}", e);
             eprintln!("Original error: {:?}", error);
         }
+    } else if let poise::FrameworkError::ArgumentParse {error, ctx, .. } = error {
+        // Argument types like durations explain what they expected, so pass that on
+        if let Err(e) = ctx.say(format!("```diff\n- {}\n```", error)).await {
+            eprintln!("Error in error handling: {:?}", e);
+            eprintln!("Original error: {:?}", error);
+        }
     } else {
         eprintln!("Couldn't handle ban error: {}", error);
     }
@@ -30,9 +44,36 @@ pub fn generate_commands() -> Vec<poise::Command<Data, anyhow::Error>> {
         moderation::ban(),
         moderation::dunce(),
         moderation::undunce(),
+        moderation::warn(),
+        moderation::cases(),
+        moderation::note(),
+        moderation::alt(),
+        moderation::dunce_hour_menu(),
+        moderation::dunce_day_menu(),
+        moderation::warn_menu(),
+        moderation::ban_menu(),
+        moderation::cases_menu(),
+        moderation::cleanup_after_menu(),
+        moderation::dunced(),
         moderation::cleanup(),
+        moderation::purgeuser(),
+        moderation::slowmode(),
+        moderation::reply(),
+        moderation::close(),
+        moderation::modmail(),
+        moderation::report(),
+        moderation::report_message(),
+        reminders::remindme(),
+        reminders::reminders(),
+        role_menus::rolemenu(),
         development::register_commands()
     ];
 
-    return commands
+    commands
+}
+
+/// Routes for the buttons, select menus and modals that commands post, see [`crate::events::router`]
+pub fn generate_routes() -> Vec<Route> {
+    [moderation::appeal_routes(), moderation::report_routes(), role_menus::routes()]
+        .into_iter().flatten().collect()
 }
@@ -0,0 +1,183 @@
+use std::collections::HashSet;
+
+use chrono::Utc;
+use poise::serenity_prelude::{Mentionable, User, UserId};
+
+use luma1_data::sea_orm::{ColumnTrait, ConnectionTrait, Condition, EntityTrait, QueryFilter, Set, sea_query};
+
+use crate::context::Context;
+use crate::entity::alt_links;
+use crate::entity::prelude::*;
+
+/// Order a pair of accounts the way links are stored
+fn link_key(a: UserId, b: UserId) -> (i64, i64) {
+    let (a, b) = (a.get() as i64, b.get() as i64);
+    (a.min(b), a.max(b))
+}
+
+/// Longest the list of linked accounts in a warning can be
+const WARNING_LENGTH: usize = 500;
+
+/// Every account linked to a user, directly or through other alts
+pub async fn linked_accounts(db: &impl ConnectionTrait, user_id: UserId) -> anyhow::Result<Vec<UserId>> {
+    let start = user_id.get() as i64;
+    let mut seen: HashSet<i64> = HashSet::from([start]);
+    let mut frontier: Vec<i64> = vec![start];
+    let mut linked = vec![];
+
+    // Look up a whole level of the link graph at a time
+    while !frontier.is_empty() {
+        let links = AltLinks::find()
+            .filter(Condition::any()
+                .add(alt_links::Column::UserId.is_in(frontier.clone()))
+                .add(alt_links::Column::AltUserId.is_in(frontier.clone())))
+            .all(db).await?;
+
+        frontier.clear();
+        for link in links {
+            for other in [link.user_id, link.alt_user_id] {
+                if seen.insert(other) {
+                    frontier.push(other);
+                    linked.push(UserId::new(other as u64));
+                }
+            }
+        }
+    }
+
+    Ok(linked)
+}
+
+/// Join lines into a list no longer than `max_length`, ending with how many were left out
+pub fn join_within(lines: &[String], separator: &str, max_length: usize) -> String {
+    let mut joined = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let separator = if i == 0 { "" } else { separator };
+        // Leave room for the line saying how many were left out
+        if joined.len() + separator.len() + line.len() + 32 > max_length {
+            joined.push_str(&format!("{}...and {} more", separator, lines.len() - i));
+            break;
+        }
+        joined.push_str(separator);
+        joined.push_str(line);
+    }
+
+    joined
+}
+
+/// A warning for moderators acting on an account with known alts.
+/// The lookup is only informational, so a failed one becomes part of the warning rather than an error.
+pub async fn linked_accounts_warning(db: &impl ConnectionTrait, user_id: UserId) -> Option<String> {
+    let linked = match linked_accounts(db, user_id).await {
+        Ok(linked) => linked,
+        Err(e) => return Some(format!("⚠️ Alts lookup failed: {:#}", e))
+    };
+    if linked.is_empty() {
+        return None;
+    }
+
+    let accounts: Vec<String> = linked.iter().map(|id| format!("{} ({})", id.mention(), id)).collect();
+    Some(format!("⚠️ Linked accounts: {}", join_within(&accounts, ", ", WARNING_LENGTH)))
+}
+
+/// Keep track of alt accounts
+#[poise::command(
+slash_command,
+required_permissions = "MODERATE_MEMBERS",
+subcommands("alt_link", "alt_unlink", "alt_list"),
+subcommand_required
+)]
+pub async fn alt(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Link two accounts as belonging to the same person
+#[poise::command(
+slash_command,
+rename = "link",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn alt_link(
+    ctx: Context<'_>,
+    #[description = "An account"] user: User,
+    #[description = "An alt of the account"] alt: User,
+    #[description = "Why they are believed to be the same person"] reason: Option<String>
+) -> anyhow::Result<()> {
+    if user.id == alt.id {
+        anyhow::bail!("An account can't be its own alt");
+    }
+
+    let (user_id, alt_user_id) = link_key(user.id, alt.id);
+    AltLinks::insert(alt_links::ActiveModel {
+        user_id: Set(user_id),
+        alt_user_id: Set(alt_user_id),
+        linked_by: Set(ctx.author().id.get() as i64),
+        linked_at: Set(Utc::now()),
+        reason: Set(reason.clone())
+    }).on_conflict(
+        sea_query::OnConflict::columns([alt_links::Column::UserId, alt_links::Column::AltUserId])
+            .update_columns([alt_links::Column::LinkedBy, alt_links::Column::LinkedAt, alt_links::Column::Reason])
+            .to_owned()
+    ).exec(&ctx.data().db).await?;
+
+    super::send_mod_action_log(ctx.http(), ctx.author().clone(), |embed| {
+        embed.description(format!("Linked {} ({}) and {} ({}) as alts", user.mention(), user.id, alt.mention(), alt.id))
+            .field("Reason", reason.clone().unwrap_or("*No reason specified*".to_string()), false)
+    }).await?;
+
+    ctx.say(format!("Linked {} ({}) and {} ({}) as alts", user.mention(), user.id, alt.mention(), alt.id)).await?;
+
+    Ok(())
+}
+
+/// Remove a link between two accounts
+#[poise::command(
+slash_command,
+rename = "unlink",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn alt_unlink(
+    ctx: Context<'_>,
+    #[description = "An account"] user: User,
+    #[description = "The account to unlink from it"] alt: User
+) -> anyhow::Result<()> {
+    let (user_id, alt_user_id) = link_key(user.id, alt.id);
+    let deleted = AltLinks::delete_by_id((user_id, alt_user_id))
+        .exec(&ctx.data().db).await?;
+    if deleted.rows_affected == 0 {
+        ctx.say(format!("```diff\n- {} ({}) and {} ({}) are not linked.\n```", user.mention(), user.id, alt.mention(), alt.id)).await?;
+        return Ok(());
+    }
+
+    super::send_mod_action_log(ctx.http(), ctx.author().clone(), |embed| {
+        embed.description(format!("Unlinked {} ({}) and {} ({})", user.mention(), user.id, alt.mention(), alt.id))
+    }).await?;
+
+    ctx.say(format!("Unlinked {} ({}) and {} ({})", user.mention(), user.id, alt.mention(), alt.id)).await?;
+
+    Ok(())
+}
+
+/// List the accounts linked to a user
+#[poise::command(
+slash_command,
+rename = "list",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn alt_list(
+    ctx: Context<'_>,
+    #[description = "User to look up"] user: User
+) -> anyhow::Result<()> {
+    let linked = linked_accounts(&ctx.data().db, user.id).await?;
+    if linked.is_empty() {
+        ctx.say(format!("No accounts are linked to {} ({})", user.mention(), user.id)).await?;
+        return Ok(());
+    }
+
+    ctx.say(format!("**Accounts linked to {} ({})**\n{}", user.mention(), user.id,
+        linked.iter().map(|id| format!("{} ({})", id.mention(), id)).collect::<Vec<_>>().join("\n"))).await?;
+
+    Ok(())
+}
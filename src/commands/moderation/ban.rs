@@ -1,18 +1,50 @@
+use chrono::Utc;
 use poise::serenity_prelude::*;
-use crate::context::{Context, P2SR_SERVER};
 
+use luma1_data::sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, Set, sea_query};
 
-async fn send_dm_notification(user: &User, reason: Option<&String>, ctx: &Context<'_>) -> anyhow::Result<()> {
-    let embed = CreateEmbed::new()
+use crate::context::{Context, P2SR_SERVER};
+use crate::entity::ban_history;
+use crate::entity::prelude::*;
+use crate::events::appeals::{decide_appeal, discuss_appeal, open_decision, start_appeal, submit_appeal,
+    ACCEPT_PREFIX, ACCEPT_SUBMIT_PREFIX, APPEAL_START_ID, APPEAL_SUBMIT_ID, DENY_PREFIX, DENY_SUBMIT_PREFIX, DISCUSS_PREFIX};
+use crate::events::router::{Route, RouteHandler};
+
+fn dm_embed(reason: Option<&String>, appeals_enabled: bool) -> CreateEmbed {
+    CreateEmbed::new()
         .color(Color::from_rgb(179, 38, 255))
         .title("You have been banned by a moderator")
         .field("Reason", reason.map(|s| s.as_str()).unwrap_or("*No reason specified, contact moderators for more information*"), false)
-        .field("Appeal", "https://s.portal2.sr/appeal", false)
+        .field("Appeal", if appeals_enabled {
+            "Press the button below, or send me a message, to appeal this ban"
+        } else {
+            "https://s.portal2.sr/appeal"
+        }, false)
         .footer(CreateEmbedFooter::new("Portal 2 Speedrun Server"))
-        .timestamp(Timestamp::now());
+        .timestamp(Timestamp::now())
+}
+
+async fn send_dm_notification(user: &User, reason: Option<&String>, ctx: &Context<'_>) -> anyhow::Result<()> {
+    let appeals_enabled = ctx.data().appeal_channel.is_some();
+    let mut message = CreateMessage::new().embed(dm_embed(reason, appeals_enabled));
+    if appeals_enabled {
+        message = message.components(vec![crate::events::appeals::appeal_button()]);
+    }
 
     let channel = user.create_dm_channel(ctx.http()).await?;
-    channel.send_message(ctx.http(), CreateMessage::new().embed(embed)).await?;
+    channel.send_message(ctx.http(), message).await?;
+
+    Ok(())
+}
+
+/// Mark a user's active ban records as ended
+pub async fn end_ban_records(db: &impl ConnectionTrait, user_id: UserId, unbanned_by: UserId) -> anyhow::Result<()> {
+    BanHistory::update_many()
+        .col_expr(ban_history::Column::UnbannedAt, sea_query::Expr::value(Utc::now()))
+        .col_expr(ban_history::Column::UnbannedBy, sea_query::Expr::value(unbanned_by.get() as i64))
+        .filter(ban_history::Column::UserId.eq(user_id.get() as i64))
+        .filter(ban_history::Column::UnbannedAt.is_null())
+        .exec(db).await?;
 
     Ok(())
 }
@@ -27,8 +59,39 @@ pub async fn ban(
     ctx: Context<'_>,
     #[description = "User to ban"] user: User,
     #[description = "Reason to record/DM"] reason: Option<String>,
-    #[description = "Delete messages from the last X days"] #[max = 7] cleanup: Option<u8>
+    #[description = "Delete messages from the last X days"] #[max = 7] cleanup: Option<u8>,
+    #[description = "Show what would happen without banning"] dry_run: Option<bool>
 ) -> anyhow::Result<()> {
+    apply_ban(ctx, user, reason, cleanup.unwrap_or(0), dry_run.unwrap_or(false)).await
+}
+
+/// Ban a user, deleting their messages from the last `cleanup` days
+pub async fn apply_ban(
+    ctx: Context<'_>,
+    user: User,
+    reason: Option<String>,
+    cleanup: u8,
+    dry_run: bool
+) -> anyhow::Result<()> {
+    super::checks::ensure_can_moderate(ctx, &user).await?;
+
+    let linked_warning = super::alts::linked_accounts_warning(&ctx.data().db, user.id).await;
+
+    let mut summary = CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .title("Ban user?")
+        .description(format!("{} ({})", user.mention(), user.id))
+        .field("Reason", reason.clone().unwrap_or("*No reason specified*".to_string()), false)
+        .field("Delete messages", format!("Last {} days", cleanup), true);
+    if let Some(warning) = &linked_warning {
+        summary = summary.field("Alts", warning, false);
+    }
+    if dry_run {
+        return super::confirm::show_dry_run(ctx, summary, Some(dm_embed(reason.as_ref(), ctx.data().appeal_channel.is_some())), vec![]).await;
+    }
+    if !super::confirm::confirm(ctx, "ban", summary).await? {
+        return Ok(());
+    }
 
     // Try to notify mod actions
     super::send_mod_action_log(ctx.http(), ctx.author().clone(), |embed| {
@@ -40,14 +103,52 @@ pub async fn ban(
     let dm_result = send_dm_notification(&user, reason.as_ref(), &ctx).await;
 
     // Try to ban the user
-    ctx.http().ban_user(P2SR_SERVER, user.id, cleanup.unwrap_or(0), reason.as_deref()).await
+    ctx.http().ban_user(P2SR_SERVER, user.id, cleanup, reason.as_deref()).await
         .map_err(|e| anyhow::Error::new(e).context("Could not ban user"))?;
 
+    // Record the ban in the user's history
+    let record_result = ban_history::ActiveModel {
+        user_id: Set(user.id.get() as i64),
+        moderator_id: Set(ctx.author().id.get() as i64),
+        reason: Set(reason.clone()),
+        banned_at: Set(Utc::now()),
+        unbanned_at: Set(None),
+        unbanned_by: Set(None),
+        ..Default::default()
+    }.insert(&ctx.data().db).await;
+
     // Send followup
-    if dm_result.is_ok() {
-        ctx.say(format!("Banned user {} ({})", user.mention(), user.id)).await?;
-    } else {
-        ctx.say(format!("Banned user {} ({})\n```diff\n- Unable to DM User\n```", user.mention(), user.id)).await?;
+    let mut response = format!("Banned user {} ({})", user.mention(), user.id);
+    if dm_result.is_err() {
+        response.push_str("\n```diff\n- Unable to DM User\n```");
+    }
+    if let Err(e) = record_result {
+        response.push_str(&format!("\n```diff\n- {:#}\n```", anyhow::Error::new(e).context("Could not record the ban")));
     }
+    if let Some(warning) = linked_warning {
+        response.push_str(&format!("\n{}", warning));
+    }
+    ctx.say(response).await?;
     Ok(())
 }
+
+/// The buttons and modals that make up the appeal workflow
+pub fn routes() -> Vec<Route> {
+    vec![
+        Route { name: APPEAL_START_ID, handler: RouteHandler::Component(|ctx, data, component, _| Box::pin(start_appeal(ctx, data, component))) },
+        Route { name: APPEAL_SUBMIT_ID, handler: RouteHandler::Modal(|ctx, data, modal, _| Box::pin(submit_appeal(ctx, data, modal))) },
+        Route { name: ACCEPT_PREFIX, handler: RouteHandler::Component(|ctx, data, component, args| Box::pin(async move {
+            open_decision(ctx, data, component, args.get(0)?, true).await
+        })) },
+        Route { name: DENY_PREFIX, handler: RouteHandler::Component(|ctx, data, component, args| Box::pin(async move {
+            open_decision(ctx, data, component, args.get(0)?, false).await
+        })) },
+        Route { name: DISCUSS_PREFIX, handler: RouteHandler::Component(|ctx, _, component, _| Box::pin(discuss_appeal(ctx, component))) },
+        Route { name: ACCEPT_SUBMIT_PREFIX, handler: RouteHandler::Modal(|ctx, data, modal, args| Box::pin(async move {
+            decide_appeal(ctx, data, modal, args.get(0)?, true).await
+        })) },
+        Route { name: DENY_SUBMIT_PREFIX, handler: RouteHandler::Modal(|ctx, data, modal, args| Box::pin(async move {
+            decide_appeal(ctx, data, modal, args.get(0)?, false).await
+        })) },
+    ]
+}
@@ -0,0 +1,134 @@
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{Mentionable, User};
+
+use luma1_data::sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+
+use crate::context::Context;
+use crate::entity::{ban_appeals, ban_history, dunce_history, reports, warnings};
+use crate::entity::ban_appeals::AppealStatus;
+use crate::entity::reports::ReportStatus;
+use crate::entity::prelude::*;
+
+/// Most cases listed on each page
+const ENTRIES_PER_PAGE: usize = 10;
+/// Longest a page can be, the limit of a message
+const PAGE_LENGTH: usize = 2000;
+/// Longest a reason can be before it's cut off
+const REASON_LENGTH: usize = 150;
+
+/// Cut a reason short enough that a page of cases fits in a message
+fn or_no_reason(reason: Option<&str>) -> String {
+    match reason {
+        Some(reason) if reason.chars().count() > REASON_LENGTH =>
+            format!("{}...", reason.chars().take(REASON_LENGTH).collect::<String>()),
+        Some(reason) => reason.to_string(),
+        None => "*No reason specified*".to_string()
+    }
+}
+
+/// Show a user's moderation history and the notes kept about them
+#[poise::command(
+slash_command,
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn cases(
+    ctx: Context<'_>,
+    #[description = "User to look up"] user: User
+) -> anyhow::Result<()> {
+    show_cases(ctx, user).await
+}
+
+/// List a user's moderation history and notes
+pub async fn show_cases(ctx: Context<'_>, user: User) -> anyhow::Result<()> {
+    let db = &ctx.data().db;
+    let user_id = user.id.get() as i64;
+
+    let mut entries: Vec<(DateTime<Utc>, String)> = vec![];
+
+    for dunce in DunceHistory::find().filter(dunce_history::Column::UserId.eq(user_id)).all(db).await? {
+        let status = match dunce.ended_at {
+            Some(ended_at) => format!("ended <t:{}:d>", ended_at.timestamp()),
+            None => format!("active until <t:{}:f>", dunce.undunce_instant.timestamp())
+        };
+        entries.push((dunce.dunced_at, format!("<t:{}:d> **Dunce** by <@{}>, {} - {}",
+            dunce.dunced_at.timestamp(), dunce.moderator_id, status, or_no_reason(dunce.reason.as_deref()))));
+    }
+
+    for warning in Warnings::find().filter(warnings::Column::UserId.eq(user_id)).all(db).await? {
+        entries.push((warning.warned_at, format!("<t:{}:d> **Warning** by <@{}> - {}",
+            warning.warned_at.timestamp(), warning.moderator_id, or_no_reason(Some(&warning.reason)))));
+    }
+
+    for ban in BanHistory::find().filter(ban_history::Column::UserId.eq(user_id)).all(db).await? {
+        let status = match (ban.unbanned_at, ban.unbanned_by) {
+            (Some(unbanned_at), Some(moderator_id)) => format!("unbanned by <@{}> <t:{}:d>", moderator_id, unbanned_at.timestamp()),
+            (Some(unbanned_at), None) => format!("unbanned <t:{}:d>", unbanned_at.timestamp()),
+            (None, _) => "active".to_string()
+        };
+        entries.push((ban.banned_at, format!("<t:{}:d> **Ban** by <@{}>, {} - {}",
+            ban.banned_at.timestamp(), ban.moderator_id, status, or_no_reason(ban.reason.as_deref()))));
+    }
+
+    for appeal in BanAppeals::find().filter(ban_appeals::Column::UserId.eq(user_id)).all(db).await? {
+        let status = match (appeal.status, appeal.decided_by) {
+            (AppealStatus::Pending, _) => "pending".to_string(),
+            (AppealStatus::Accepted, Some(moderator_id)) => format!("accepted by <@{}>", moderator_id),
+            (AppealStatus::Denied, Some(moderator_id)) => format!("denied by <@{}>", moderator_id),
+            (AppealStatus::Accepted, None) => "accepted".to_string(),
+            (AppealStatus::Denied, None) => "denied".to_string()
+        };
+        entries.push((appeal.submitted_at, format!("<t:{}:d> **Ban appeal** #{}, {}",
+            appeal.submitted_at.timestamp(), appeal.id, status)));
+    }
+
+    for report in Reports::find().filter(reports::Column::ReportedUserId.eq(user_id)).all(db).await? {
+        let status = match report.status {
+            ReportStatus::Open => "open".to_string(),
+            _ => report.resolution.as_deref().map(|resolution| or_no_reason(Some(resolution))).unwrap_or_default()
+        };
+        entries.push((report.created_at, format!("<t:{}:d> **Report** #{}, {}",
+            report.created_at.timestamp(), report.id, status)));
+    }
+
+    // Newest first
+    entries.sort_by_key(|(time, _)| std::cmp::Reverse(*time));
+
+    let notes = super::notes::user_notes(db, user.id).await?;
+    let mut header = format!("**Cases for {} ({})**", user.mention(), user.id);
+    if let Some(warning) = super::alts::linked_accounts_warning(db, user.id).await {
+        header.push_str(&format!("\n{}", warning));
+    }
+    if !notes.is_empty() {
+        header.push_str(&format!("\n__Notes__\n{}", super::notes::summarize_notes(&notes)));
+    }
+
+    // Fill each page up to the entry or length limit, whichever comes first
+    let total = entries.len();
+    let mut pages: Vec<String> = vec![];
+    let mut page = String::new();
+    let mut page_entries = 0;
+    for (_, entry) in entries {
+        if page_entries > 0 && (page_entries == ENTRIES_PER_PAGE || page.len() + entry.len() + 1 > PAGE_LENGTH) {
+            pages.push(std::mem::take(&mut page));
+            page_entries = 0;
+        }
+        if page_entries == 0 {
+            page = format!("{}\n__History ({})__", header, total);
+        }
+        page.push_str(&format!("\n{}", entry));
+        page_entries += 1;
+    }
+    if page_entries > 0 {
+        pages.push(page);
+    }
+    if pages.is_empty() {
+        pages.push(format!("{}\nNo moderation history", header));
+    }
+
+    let pages: Vec<&str> = pages.iter().map(|page| page.as_str()).collect();
+    poise::builtins::paginate(ctx, &pages).await?;
+
+    Ok(())
+}
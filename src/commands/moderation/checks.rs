@@ -0,0 +1,46 @@
+use anyhow::bail;
+use poise::serenity_prelude::{Http, Mentionable, User, UserId};
+
+use crate::context::{Context, P2SR_SERVER};
+use super::roles::highest_position;
+
+/// Refuse to run a moderation command against someone the moderator shouldn't be able to act on:
+/// themselves, the bot, the server owner, or anyone with a role at or above the moderator's highest role.
+/// Bot owners are allowed to act on anyone.
+pub async fn ensure_can_moderate(ctx: Context<'_>, target: &User) -> anyhow::Result<()> {
+    if ctx.framework().options().owners.contains(&ctx.author().id) {
+        return Ok(());
+    }
+
+    check_can_moderate(ctx.http(), ctx.framework().bot_id, ctx.author().id, target).await
+}
+
+/// The checks behind [`ensure_can_moderate`], for moderation actions taken outside of a command
+pub async fn check_can_moderate(http: &Http, bot_id: UserId, moderator_id: UserId, target: &User) -> anyhow::Result<()> {
+    if target.id == moderator_id {
+        bail!("You can't use moderation commands on yourself");
+    }
+    if target.id == bot_id {
+        bail!("You can't use moderation commands on the bot");
+    }
+
+    let guild = P2SR_SERVER.to_partial_guild(http).await?;
+    if target.id == guild.owner_id {
+        bail!("You can't use moderation commands on the server owner");
+    }
+    if moderator_id == guild.owner_id {
+        return Ok(());
+    }
+
+    // Users who aren't in the server have no roles to compare
+    let Ok(target_member) = P2SR_SERVER.member(http, target.id).await else {
+        return Ok(());
+    };
+    let moderator_member = P2SR_SERVER.member(http, moderator_id).await?;
+
+    if highest_position(&guild.roles, &target_member.roles) >= highest_position(&guild.roles, &moderator_member.roles) {
+        bail!("{} has a role at or above your highest role", target.mention());
+    }
+
+    Ok(())
+}
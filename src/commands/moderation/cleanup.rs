@@ -1,52 +1,345 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
 use chrono::{TimeDelta, Utc};
 use crate::context::Context;
+use poise::{CreateReply, ReplyHandle};
+use poise::serenity_prelude::{ButtonStyle, ChannelId, Color, ComponentInteractionCollector, CreateActionRow, CreateAllowedMentions, CreateButton, CreateEmbed, CreateInteractionResponse, CreateMessage, GetMessages, Http, HttpError, Mentionable, MessageId, StatusCode, User, UserId};
 use serenity::model::channel::Message;
-use poise::futures_util::StreamExt;
+use poise::futures_util::{FutureExt, Stream, StreamExt};
+use regex::Regex;
+
+/// Upper bound on how far back a single cleanup will look for matching messages
+const MAX_SCANNED_MESSAGES: u32 = 10_000;
+/// How often the progress message of a running cleanup is updated
+pub const PROGRESS_INTERVAL: Duration = Duration::from_secs(3);
+/// How many times a delete request is attempted before giving up on those messages
+const MAX_DELETE_ATTEMPTS: u32 = 3;
+
+/// Criteria a message has to meet to be removed by a cleanup
+#[derive(Default)]
+pub struct MessageFilter {
+    pub author: Option<UserId>,
+    pub bots_only: bool,
+    pub contains: Option<String>,
+    pub pattern: Option<Regex>,
+    pub attachments_only: bool,
+    pub links_only: bool,
+}
+
+impl MessageFilter {
+    pub fn matches(&self, message: &Message) -> bool {
+        // Don't delete pinned messages
+        if message.pinned {
+            return false;
+        }
+
+        if self.author.is_some_and(|author| author != message.author.id) {
+            return false;
+        }
+        if self.bots_only && !message.author.bot {
+            return false;
+        }
+        if let Some(contains) = &self.contains
+            && !message.content.to_lowercase().contains(&contains.to_lowercase()) {
+            return false;
+        }
+        if let Some(pattern) = &self.pattern
+            && !pattern.is_match(&message.content) {
+            return false;
+        }
+        if self.attachments_only && message.attachments.is_empty() {
+            return false;
+        }
+        if self.links_only && !(message.content.contains("http://") || message.content.contains("https://")) {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// Stream the messages of a channel from newest to oldest, optionally starting before a given message
+pub fn message_history(
+    http: &Http,
+    channel_id: ChannelId,
+    before: Option<MessageId>
+) -> impl Stream<Item = serenity::Result<Message>> + '_ {
+    struct State {
+        buffer: VecDeque<Message>,
+        before: Option<MessageId>,
+        exhausted: bool,
+    }
+
+    let init_state = State { buffer: VecDeque::new(), before, exhausted: false };
+
+    poise::futures_util::stream::unfold(init_state, move |mut state| async move {
+        if state.buffer.is_empty() && !state.exhausted {
+            let mut builder = GetMessages::new().limit(100);
+            if let Some(before) = state.before {
+                builder = builder.before(before);
+            }
+
+            match channel_id.messages(http, builder).await {
+                Ok(messages) => {
+                    state.exhausted = messages.len() < 100;
+                    state.before = messages.last().map(|m| m.id);
+                    state.buffer.extend(messages);
+                }
+                Err(e) => {
+                    state.exhausted = true;
+                    return Some((Err(e), state));
+                }
+            }
+        }
+
+        state.buffer.pop_front().map(|message| (Ok(message), state))
+    })
+}
+
+/// Split messages into the requests needed to delete them, bulk deleting wherever Discord allows it
+pub fn deletion_batches(messages: &[Message]) -> Vec<Vec<MessageId>> {
+    // Messages can only be bulk deleted if they are younger than 2 weeks old,
+    // so if they're older, individually delete
+    let (to_bulk_delete, to_delete): (Vec<&Message>, Vec<&Message>) = messages.iter()
+        .partition(|message| Utc::now().signed_duration_since(*message.timestamp) < TimeDelta::weeks(2));
+
+    to_bulk_delete.chunks(100)
+        .map(|chunk| chunk.iter().map(|message| message.id).collect())
+        .chain(to_delete.iter().map(|message| vec![message.id]))
+        .collect()
+}
+
+/// Whether a failed request is worth trying again
+fn is_transient(error: &serenity::Error) -> bool {
+    match error {
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) =>
+            response.status_code == StatusCode::TOO_MANY_REQUESTS || response.status_code.is_server_error(),
+        serenity::Error::Http(HttpError::Request(_)) => true,
+        _ => false
+    }
+}
+
+/// Delete one batch from [`deletion_batches`], backing off and retrying when Discord is rate limiting us
+pub async fn delete_batch(http: &Http, channel_id: ChannelId, batch: &[MessageId]) -> serenity::Result<()> {
+    let mut attempt = 1;
+    loop {
+        match channel_id.delete_messages(http, batch).await {
+            Err(e) if attempt < MAX_DELETE_ATTEMPTS && is_transient(&e) => {
+                tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+                attempt += 1;
+            }
+            result => return result
+        }
+    }
+}
+
+/// A progress message, with a cancel button while there's still something to cancel
+pub fn progress_reply(content: String, cancel_id: Option<&str>) -> CreateReply {
+    let components = match cancel_id {
+        Some(cancel_id) => vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(cancel_id).label("Cancel").style(ButtonStyle::Danger)
+        ])],
+        None => vec![]
+    };
+
+    CreateReply::default().content(content).components(components)
+}
+
+/// Update the progress message of a cleanup, which can fail without stopping the cleanup
+/// (e.g. once the interaction token has expired on a long cleanup)
+pub async fn show_progress(ctx: Context<'_>, progress: &ReplyHandle<'_>, content: String, cancel_id: Option<&str>) -> bool {
+    match progress.edit(ctx, progress_reply(content, cancel_id)).await {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Failed to update progress message: {:?}", e);
+            false
+        }
+    }
+}
 
-/// Delete recent messages in the channel
+/// Delete messages in the channel, optionally only those matching filters
 #[poise::command(
 slash_command,
 required_permissions = "MANAGE_MESSAGES",
 on_error = "crate::commands::error_handler",
 ephemeral = true
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn cleanup(
     ctx: Context<'_>,
-    #[description = "Number of messages to delete"] #[min = 2] #[max = 1000] messages: u32,
+    #[description = "Number of matching messages to delete"] #[min = 1] #[max = 1000] messages: u32,
+    #[description = "Only delete messages from this user"] user: Option<User>,
+    #[description = "Only delete messages from bots"] bots: Option<bool>,
+    #[description = "Only delete messages containing this text (case-insensitive)"] contains: Option<String>,
+    #[description = "Only delete messages matching this regex"] regex: Option<String>,
+    #[description = "Only delete messages with attachments"] attachments: Option<bool>,
+    #[description = "Only delete messages with links"] links: Option<bool>,
+    #[description = "Only delete messages sent after this message ID"] after: Option<MessageId>,
+    #[description = "Only delete messages sent before this message ID"] before: Option<MessageId>,
+    #[description = "Show which messages would be deleted without deleting them"] dry_run: Option<bool>
 ) -> anyhow::Result<()> {
     ctx.defer_ephemeral().await?;
 
-    let mut total_deleted = 0;
-    let mut to_bulk_delete = Vec::new();
+    if let Some(user) = &user {
+        super::checks::ensure_can_moderate(ctx, user).await?;
+    }
+
+    let pattern = match regex {
+        Some(regex) => Some(Regex::new(&regex)
+            .map_err(|e| anyhow::Error::new(e).context("Invalid regex"))?),
+        None => None
+    };
+
+    let filter = MessageFilter {
+        author: user.map(|user| user.id),
+        bots_only: bots.unwrap_or(false),
+        contains,
+        pattern,
+        attachments_only: attachments.unwrap_or(false),
+        links_only: links.unwrap_or(false),
+    };
+
+    run_cleanup(ctx, messages, filter, after, before, dry_run.unwrap_or(false)).await
+}
+
+/// Delete up to `messages` messages in the current channel matching the filter, between `after` and `before`
+pub async fn run_cleanup(
+    ctx: Context<'_>,
+    messages: u32,
+    filter: MessageFilter,
+    after: Option<MessageId>,
+    before: Option<MessageId>,
+    dry_run: bool
+) -> anyhow::Result<()> {
+    let cancel_id = format!("{}-cleanup-cancel", ctx.id());
+    let progress = ctx.send(progress_reply("Scanning messages...".to_string(), Some(&cancel_id))).await?;
+    let mut cancel_stream = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .custom_ids(vec![cancel_id.clone()])
+        .stream()
+        .boxed();
+    let mut last_progress_update = Instant::now();
 
-    let mut message_stream = ctx.channel_id().messages_iter(ctx.http()).boxed();
-    while let Some(message) = message_stream.next().await {
+    let mut total_scanned = 0;
+    let mut matched = Vec::new();
+
+    let mut message_stream = message_history(ctx.http(), ctx.channel_id(), before).boxed();
+    loop {
+        if let Some(Some(interaction)) = cancel_stream.next().now_or_never() {
+            let _ = interaction.create_response(ctx, CreateInteractionResponse::Acknowledge).await;
+            show_progress(ctx, &progress,
+                format!("Cancelled, nothing was deleted (scanned {})", total_scanned), None).await;
+            return Ok(());
+        }
+        let Some(message) = message_stream.next().await else {
+            break;
+        };
         let message: Message = message?;
-        // Don't delete pinned messages
-        if !message.pinned {
-            // Messages can only be bulk deleted if they are younger than 2 weeks old,
-            // so if they're older, individually delete
-            if Utc::now().signed_duration_since(&*message.timestamp) < TimeDelta::weeks(2) {
-                to_bulk_delete.push(message.id);
-            } else {
-                message.delete(ctx.http()).await?;
-            }
-            total_deleted += 1;
+
+        // Everything past this point is older than the requested range
+        if after.is_some_and(|after| message.id <= after) {
+            break;
+        }
+
+        total_scanned += 1;
+        if filter.matches(&message) {
+            matched.push(message);
         }
 
-        if total_deleted >= messages {
+        if matched.len() as u32 >= messages || total_scanned >= MAX_SCANNED_MESSAGES {
             break;
         }
+
+        if last_progress_update.elapsed() >= PROGRESS_INTERVAL {
+            show_progress(ctx, &progress,
+                format!("Scanning messages... scanned {}, matched {}/{}", total_scanned, matched.len(), messages),
+                Some(&cancel_id)).await;
+            last_progress_update = Instant::now();
+        }
     }
 
-    // Bulk delete messages that can be bulk deleted
-    for bulk_chunk in to_bulk_delete.chunks(100) {
-        ctx.channel_id().delete_messages(ctx.http(), bulk_chunk).await?;
+    if matched.is_empty() {
+        show_progress(ctx, &progress,
+            format!("No matching messages found (scanned {})", total_scanned), None).await;
+        return Ok(());
     }
 
-    ctx.reply(format!("Removed {} messages", total_deleted)).await?
-        .delete(ctx).await?;
+    let channel_id = ctx.channel_id();
+    let total_matched = matched.len();
+
+    show_progress(ctx, &progress,
+        format!("Found {} matching messages (scanned {})", total_matched, total_scanned), None).await;
+    let summary = CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .title("Delete messages?")
+        .description(format!("Delete {} messages in {}", total_matched, channel_id.mention()));
+    if dry_run {
+        let transcript = super::transcript::transcript_attachment(super::transcript::build_transcript(channel_id, &matched));
+        return super::confirm::show_dry_run(ctx, summary, None, vec![transcript]).await;
+    }
+    if !super::confirm::confirm(ctx, "cleanup", summary).await? {
+        show_progress(ctx, &progress,
+            format!("Cancelled, nothing was deleted (scanned {})", total_scanned), None).await;
+        return Ok(());
+    }
+    show_progress(ctx, &progress, "Deleting messages...".to_string(), Some(&cancel_id)).await;
+
+    // Keep a record of what is about to be removed before anything is deleted
+    super::send_mod_action_log_with_files(
+        ctx.http(),
+        ctx.author().clone(),
+        vec![super::transcript::transcript_attachment(super::transcript::build_transcript(channel_id, &matched))],
+        |embed| embed.description(format!("Cleaned up {} messages in {}", total_matched, channel_id.mention()))
+    ).await?;
+
+    let mut total_deleted = 0;
+    let mut total_failed = 0;
+    let mut cancelled = false;
+    for batch in deletion_batches(&matched) {
+        // Only stop between batches, so we always know which messages were deleted
+        if let Some(Some(interaction)) = cancel_stream.next().now_or_never() {
+            let _ = interaction.create_response(ctx, CreateInteractionResponse::Acknowledge).await;
+            cancelled = true;
+            break;
+        }
+
+        match delete_batch(ctx.http(), channel_id, &batch).await {
+            Ok(()) => total_deleted += batch.len(),
+            Err(e) => {
+                eprintln!("Failed to delete {} messages during cleanup: {:?}", batch.len(), e);
+                total_failed += batch.len();
+            }
+        }
+
+        if last_progress_update.elapsed() >= PROGRESS_INTERVAL {
+            show_progress(ctx, &progress,
+                format!("Deleting messages... deleted {}, remaining {}",
+                    total_deleted, total_matched - total_deleted - total_failed),
+                Some(&cancel_id)).await;
+            last_progress_update = Instant::now();
+        }
+    }
+
+    if cancelled {
+        super::send_mod_action_log(ctx.http(), ctx.author().clone(), |embed| {
+            embed.description(format!("Cancelled cleanup in {} after deleting {} of {} messages",
+                channel_id.mention(), total_deleted, total_matched))
+        }).await?;
+    }
+
+    let mut summary = format!("Removed {} messages (scanned {})", total_deleted, total_scanned);
+    if cancelled {
+        summary.push_str(&format!("\nCancelled with {} messages left", total_matched - total_deleted - total_failed));
+    }
+    if total_failed > 0 {
+        summary.push_str(&format!("\n```diff\n- Failed to delete {} messages\n```", total_failed));
+    }
+    if !show_progress(ctx, &progress, summary.clone(), None).await {
+        channel_id.send_message(ctx, CreateMessage::new()
+            .content(format!("{} {}", ctx.author().mention(), summary))
+            .allowed_mentions(CreateAllowedMentions::new().users(vec![ctx.author().id]))).await?;
+    }
 
     Ok(())
 }
-
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use poise::CreateReply;
+use poise::serenity_prelude::{ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateAttachment, CreateButton, CreateEmbed, CreateEmbedAuthor, CreateInteractionResponse, CreateInteractionResponseMessage};
+
+use crate::context::Context;
+
+/// How long a moderator has to confirm an action before it is cancelled
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Ask the moderator to confirm an action, if confirmation is configured for this command.
+/// Returns whether the action should go ahead.
+pub async fn confirm(ctx: Context<'_>, command: &str, summary: CreateEmbed) -> anyhow::Result<bool> {
+    let Some(config) = &ctx.data().confirmation else {
+        return Ok(true);
+    };
+    if !config.commands.contains(command) {
+        return Ok(true);
+    }
+
+    // Trusted moderators skip the confirmation step
+    if let Some(member) = ctx.author_member().await
+        && member.roles.iter().any(|role_id| config.trusted_roles.contains(role_id)) {
+        return Ok(true);
+    }
+
+    let confirm_id = format!("{}-confirm", ctx.id());
+    let cancel_id = format!("{}-cancel", ctx.id());
+
+    let reply = ctx.send(CreateReply::default()
+        .ephemeral(true)
+        .embed(summary.clone())
+        .components(vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(&confirm_id).label("Confirm").style(ButtonStyle::Danger),
+            CreateButton::new(&cancel_id).label("Cancel").style(ButtonStyle::Secondary)
+        ])])
+    ).await?;
+
+    let interaction = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .custom_ids(vec![confirm_id.clone(), cancel_id])
+        .timeout(CONFIRMATION_TIMEOUT)
+        .await;
+
+    let Some(interaction) = interaction else {
+        reply.edit(ctx, CreateReply::default()
+            .content("Timed out, nothing was done")
+            .embed(summary)
+            .components(vec![])
+        ).await?;
+        return Ok(false);
+    };
+
+    let confirmed = interaction.data.custom_id == confirm_id;
+    interaction.create_response(ctx, CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new()
+            .content(if confirmed { "Confirmed" } else { "Cancelled, nothing was done" })
+            .components(vec![])
+    )).await?;
+
+    Ok(confirmed)
+}
+
+/// Show what an action would do, including the DM the user would receive, without doing any of it
+pub async fn show_dry_run(
+    ctx: Context<'_>,
+    summary: CreateEmbed,
+    dm: Option<CreateEmbed>,
+    attachments: Vec<CreateAttachment>
+) -> anyhow::Result<()> {
+    let mut reply = CreateReply::default()
+        .ephemeral(true)
+        .content("**Dry run**, nothing was changed")
+        .embed(summary);
+    if let Some(dm) = dm {
+        reply = reply.embed(dm.author(CreateEmbedAuthor::new("DM that would be sent")));
+    }
+    for attachment in attachments {
+        reply = reply.attachment(attachment);
+    }
+
+    ctx.send(reply).await?;
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use poise::serenity_prelude::{Message, User};
+
+use crate::context::Data;
+use super::cleanup::MessageFilter;
+use super::dunce::DunceExpiry;
+use crate::commands::{Duration, TimeUnits};
+
+type ApplicationContext<'a> = poise::ApplicationContext<'a, Data, anyhow::Error>;
+
+/// Most messages a context-menu cleanup will delete
+const CLEANUP_LIMIT: u32 = 1000;
+
+#[derive(poise::Modal)]
+#[name = "Reason"]
+struct OptionalReasonModal {
+    #[name = "Reason to record/DM"]
+    #[paragraph]
+    #[max_length = 1000]
+    reason: Option<String>,
+}
+
+#[derive(poise::Modal)]
+#[name = "Reason"]
+struct ReasonModal {
+    #[name = "Reason to record/DM"]
+    #[paragraph]
+    #[max_length = 1000]
+    reason: String,
+}
+
+/// Ask for an optional reason. Returns `None` if the form was closed without submitting.
+async fn ask_optional_reason(ctx: ApplicationContext<'_>) -> anyhow::Result<Option<Option<String>>> {
+    Ok(poise::execute_modal(ctx, None::<OptionalReasonModal>, None).await?
+        .map(|form| form.reason))
+}
+
+async fn dunce_for(ctx: ApplicationContext<'_>, user: User, duration: Duration) -> anyhow::Result<()> {
+    let Some(reason) = ask_optional_reason(ctx).await? else {
+        return Ok(());
+    };
+
+    super::dunce::apply_dunce(ctx.into(), user, DunceExpiry::FromNow(duration), reason, false).await
+}
+
+/// Dunce a user for an hour
+#[poise::command(
+context_menu_command = "Dunce 1 hour",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn dunce_hour_menu(ctx: ApplicationContext<'_>, user: User) -> anyhow::Result<()> {
+    dunce_for(ctx, user, Duration::of(1, TimeUnits::Hours)).await
+}
+
+/// Dunce a user for a day
+#[poise::command(
+context_menu_command = "Dunce 1 day",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn dunce_day_menu(ctx: ApplicationContext<'_>, user: User) -> anyhow::Result<()> {
+    dunce_for(ctx, user, Duration::of(1, TimeUnits::Days)).await
+}
+
+/// Warn a user
+#[poise::command(
+context_menu_command = "Warn",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn warn_menu(ctx: ApplicationContext<'_>, user: User) -> anyhow::Result<()> {
+    let Some(form) = poise::execute_modal(ctx, None::<ReasonModal>, None).await? else {
+        return Ok(());
+    };
+
+    super::warn::apply_warn(ctx.into(), user, form.reason, false).await
+}
+
+/// Ban a user
+#[poise::command(
+context_menu_command = "Ban",
+required_permissions = "BAN_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn ban_menu(ctx: ApplicationContext<'_>, user: User) -> anyhow::Result<()> {
+    let Some(reason) = ask_optional_reason(ctx).await? else {
+        return Ok(());
+    };
+
+    super::ban::apply_ban(ctx.into(), user, reason, 0, false).await
+}
+
+/// Show a user's moderation history
+#[poise::command(
+context_menu_command = "View cases",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn cases_menu(ctx: ApplicationContext<'_>, user: User) -> anyhow::Result<()> {
+    super::cases::show_cases(ctx.into(), user).await
+}
+
+/// Delete a message and everything sent after it
+#[poise::command(
+context_menu_command = "Clean up messages after this",
+guild_only,
+required_permissions = "MANAGE_MESSAGES",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn cleanup_after_menu(ctx: ApplicationContext<'_>, message: Message) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+
+    // The chosen message is removed along with everything after it
+    let after = message.id.get().checked_sub(1).map(Into::into);
+    super::cleanup::run_cleanup(ctx.into(), CLEANUP_LIMIT, MessageFilter::default(), after, None, false).await
+}
@@ -1,114 +1,380 @@
 use anyhow::anyhow;
-use chrono::{DateTime, Months, TimeDelta, Utc};
-use poise::serenity_prelude::User;
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{Color, CreateEmbed, CreateEmbedFooter, CreateMessage, Timestamp, User, UserId};
 use poise::serenity_prelude::Mentionable;
 
 use luma1_data::entity::prelude::*;
-use luma1_data::sea_orm::{IntoActiveModel, EntityTrait, ColumnTrait, QueryFilter, Set, sea_query};
-use serenity::all::{RoleId};
+use luma1_data::sea_orm::{IntoActiveModel, EntityTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, QueryFilter, QueryOrder, Set, TransactionTrait, sea_query};
+use serenity::all::{EditMember, Http, Member, RoleId};
 
+use crate::commands::{Duration, When};
 use crate::context::{Context, P2SR_DUNCE_ROLE, P2SR_SERVER};
+use crate::entity::prelude::*;
 
-#[macro_export]
-macro_rules! ignore {
-    ($x:expr) => {
-        ();
-    }
+#[derive(poise::ChoiceParameter)]
+pub enum DunceMode {
+    #[name = "Set (from now)"]
+    Set,
+    #[name = "Extend existing dunce"]
+    Extend,
+    #[name = "Shorten existing dunce"]
+    Shorten
 }
 
-#[macro_export]
-macro_rules! join_and_accumulate_errors {
-    ($x:expr, $( $y:expr ),*) => {
-        let errors = tokio::join!($($y),*);
-        $(
-            ignore!($y);
-            if let Err(e) = errors.${index()} {
-               $x.push(format!("- {:#}", e));
+/// How a dunce's expiry should be decided
+pub enum DunceExpiry {
+    /// Dunce for a duration counted from now
+    FromNow(Duration),
+    /// Add a duration to the current expiry, or dunce from now if not dunced
+    Extend(Duration),
+    /// Take a duration off the current expiry
+    Shorten(Duration),
+    /// Dunce until an absolute time
+    Until(DateTime<Utc>)
+}
+
+impl DunceExpiry {
+    fn resolve(&self, current: Option<DateTime<Utc>>) -> anyhow::Result<DateTime<Utc>> {
+        let now = Utc::now();
+        let undunce_time = match self {
+            DunceExpiry::FromNow(duration) => duration.after(now)?,
+            DunceExpiry::Extend(duration) =>
+                duration.after(current.filter(|current| *current > now).unwrap_or(now))?,
+            DunceExpiry::Shorten(duration) => {
+                let current = current.ok_or(anyhow!("User is not dunced, so their dunce can't be shortened"))?;
+                duration.before(current)?
             }
-        )*
+            DunceExpiry::Until(time) => *time
+        };
+
+        if undunce_time <= now {
+            anyhow::bail!("That would end the dunce <t:{}:R>, use /undunce to end it now", undunce_time.timestamp());
+        }
+
+        Ok(undunce_time)
+    }
+
+    /// Title of the DM telling the user about the change
+    fn dm_title(&self, currently_dunced: bool) -> &'static str {
+        match (self, currently_dunced) {
+            (_, false) => "You have been dunced by a moderator",
+            (DunceExpiry::Extend(_), true) => "Your dunce has been extended by a moderator",
+            (DunceExpiry::Shorten(_), true) => "Your dunce has been shortened by a moderator",
+            (DunceExpiry::FromNow(_) | DunceExpiry::Until(_), true) => "Your dunce has been changed by a moderator"
+        }
     }
 }
 
-#[derive(poise::ChoiceParameter)]
-pub enum TimeUnits {
-    Minutes,
-    Hours,
-    Days,
-    Weeks,
-    Months
-}
-
-impl TimeUnits {
-    fn apply_delta(&self, time: DateTime<Utc>, amount: u32) -> anyhow::Result<DateTime<Utc>> {
-        let res = match &self {
-            TimeUnits::Minutes => time.checked_add_signed(TimeDelta::minutes(amount as i64)),
-            TimeUnits::Hours => time.checked_add_signed(TimeDelta::hours(amount as i64)),
-            TimeUnits::Days => time.checked_add_signed(TimeDelta::days(amount as i64)),
-            TimeUnits::Weeks => time.checked_add_signed(TimeDelta::weeks(amount as i64)),
-            TimeUnits::Months => time.checked_add_months(Months::new(amount))
-        };
+fn dm_embed(title: &str, reason: Option<&String>, undunce_time: DateTime<Utc>) -> CreateEmbed {
+    CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .title(title)
+        .field("Reason", reason.map(|s| s.as_str()).unwrap_or("*No reason specified, contact moderators for more information*"), false)
+        .field("Undunced", format!("<t:{}:f> (<t:{}:R>)", undunce_time.timestamp(), undunce_time.timestamp()), false)
+        .footer(CreateEmbedFooter::new("Portal 2 Speedrun Server"))
+        .timestamp(Timestamp::now())
+}
+
+async fn send_dm_notification(http: &Http, user: &User, embed: CreateEmbed) -> anyhow::Result<()> {
+    let channel = user.create_dm_channel(http).await?;
+    channel.send_message(http, CreateMessage::new().embed(embed)).await?;
+
+    Ok(())
+}
 
-        res.ok_or(anyhow!("Dunce time out of maximum range"))
+/// Record a dunce in the user's history, or update the active record if they're already dunced
+async fn record_dunce(
+    db: &impl ConnectionTrait,
+    user_id: UserId,
+    moderator_id: UserId,
+    reason: Option<String>,
+    undunce_time: DateTime<Utc>
+) -> anyhow::Result<()> {
+    let active_record = DunceHistory::find()
+        .filter(crate::entity::dunce_history::Column::UserId.eq(user_id.get() as i64))
+        .filter(crate::entity::dunce_history::Column::EndedAt.is_null())
+        .order_by_desc(crate::entity::dunce_history::Column::DuncedAt)
+        .one(db).await?;
+
+    match active_record {
+        Some(record) => {
+            let mut record = record.into_active_model();
+            record.undunce_instant = Set(undunce_time);
+            if reason.is_some() {
+                record.reason = Set(reason);
+            }
+            DunceHistory::update(record).exec(db).await?;
+        }
+        None => {
+            DunceHistory::insert(crate::entity::dunce_history::ActiveModel {
+                user_id: Set(user_id.into()),
+                moderator_id: Set(moderator_id.into()),
+                reason: Set(reason),
+                dunced_at: Set(Utc::now()),
+                undunce_instant: Set(undunce_time),
+                ..Default::default()
+            }).exec(db).await?;
+        }
     }
+
+    Ok(())
 }
 
-/// Dunce a user
+/// Mark a user's active dunce records as ended
+pub async fn end_dunce_records(db: &impl ConnectionTrait, user_id: UserId, ended_by: UserId) -> anyhow::Result<()> {
+    DunceHistory::update_many()
+        .col_expr(crate::entity::dunce_history::Column::EndedAt, sea_query::Expr::value(Utc::now()))
+        .col_expr(crate::entity::dunce_history::Column::EndedBy, sea_query::Expr::value(ended_by.get() as i64))
+        .filter(crate::entity::dunce_history::Column::UserId.eq(user_id.get() as i64))
+        .filter(crate::entity::dunce_history::Column::EndedAt.is_null())
+        .exec(db).await?;
+
+    Ok(())
+}
+
+/// Dunce a user, or manage an existing dunce
 #[poise::command(
 slash_command,
 required_permissions = "MODERATE_MEMBERS",
+subcommands("dunce_user", "dunce_until", "dunce_status", "super::reconcile::dunce_reconcile"),
+subcommand_required
+)]
+pub async fn dunce(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Dunce a user and DMs them a reason
+#[poise::command(
+slash_command,
+rename = "user",
+required_permissions = "MODERATE_MEMBERS",
 on_error = "crate::commands::error_handler"
 )]
-pub async fn dunce(
+pub async fn dunce_user(
     ctx: Context<'_>,
     #[description = "User to dunce"] user: User,
-    #[description = "Time to dunce"] time: u32,
-    #[description = "Time units"] time_units: TimeUnits
+    #[description = "How long to dunce, like 30m, 1d12h or 2 weeks"] duration: Duration,
+    #[description = "Whether to replace, extend or shorten an existing dunce (default: replace)"] mode: Option<DunceMode>,
+    #[description = "Reason to record/DM"] reason: Option<String>,
+    #[description = "Show what would happen without dunceing"] dry_run: Option<bool>
 ) -> anyhow::Result<()> {
-    let user_mention = user.mention();
-    let user_id = user.id;
+    let expiry = match mode.unwrap_or(DunceMode::Set) {
+        DunceMode::Set => DunceExpiry::FromNow(duration),
+        DunceMode::Extend => DunceExpiry::Extend(duration),
+        DunceMode::Shorten => DunceExpiry::Shorten(duration)
+    };
 
-    // Calculate when to undunce
-    let undunce_time = time_units.apply_delta(Utc::now(), time)?;
+    apply_dunce(ctx, user, expiry, reason, dry_run.unwrap_or(false)).await
+}
+
+/// Dunce a user until a specific time
+#[poise::command(
+slash_command,
+rename = "until",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn dunce_until(
+    ctx: Context<'_>,
+    #[description = "User to dunce"] user: User,
+    #[description = "When to undunce, as a timestamp, UTC date (YYYY-MM-DD HH:MM) or time from now"] expires: When,
+    #[description = "Reason to record/DM"] reason: Option<String>,
+    #[description = "Show what would happen without dunceing"] dry_run: Option<bool>
+) -> anyhow::Result<()> {
+    let expiry = DunceExpiry::Until(expires.resolve(Utc::now())?);
+
+    apply_dunce(ctx, user, expiry, reason, dry_run.unwrap_or(false)).await
+}
+
+/// Show whether a user is dunced, and the roles stored for them
+#[poise::command(
+slash_command,
+rename = "status",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn dunce_status(
+    ctx: Context<'_>,
+    #[description = "User to check"] user: User
+) -> anyhow::Result<()> {
+    let dunce_instant = DunceInstants::find_by_id(user.id.get() as i64)
+        .one(&ctx.data().db).await?;
+
+    let history = DunceHistory::find()
+        .filter(crate::entity::dunce_history::Column::UserId.eq(user.id.get() as i64))
+        .order_by_desc(crate::entity::dunce_history::Column::DuncedAt)
+        .all(&ctx.data().db).await?;
 
+    let stored_roles: Vec<String> = DunceStoredRoles::find()
+        .filter(luma1_data::entity::dunce_stored_roles::Column::UserId.eq(user.id.get() as i64))
+        .all(&ctx.data().db).await?
+        .iter().map(|r| RoleId::new(r.role_id as u64).mention().to_string())
+        .collect();
+
+    let mut embed = CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .title(format!("Dunce status for {}", user.name))
+        .description(format!("{} ({})", user.mention(), user.id));
+
+    match &dunce_instant {
+        Some(dunce_instant) => {
+            embed = embed
+                .field("Remaining", format!("Undunce <t:{}:R>", dunce_instant.undunce_instant.timestamp()), true)
+                .field("Expires", format!("<t:{}:f>", dunce_instant.undunce_instant.timestamp()), true);
+
+            // The active history record knows who dunced them, if it was dunced through L3ma
+            if let Some(record) = history.iter().find(|record| record.ended_at.is_none()) {
+                embed = embed
+                    .field("Dunced by", format!("<@{}>", record.moderator_id), true)
+                    .field("Dunced", format!("<t:{}:f>", record.dunced_at.timestamp()), true)
+                    .field("Reason", record.reason.clone().unwrap_or("*No reason specified*".to_string()), false);
+            }
+        }
+        None => {
+            embed = embed.field("Status", "Not dunced", false);
+        }
+    }
+
+    embed = embed
+        .field("Stored roles", if stored_roles.is_empty() { "*None*".to_string() } else { stored_roles.join(" ") }, false)
+        .field("Past dunces", history.iter().filter(|record| record.ended_at.is_some()).count().to_string(), true);
+
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Remove and add roles on a member in a single request, so either every change is made or none are
+pub async fn swap_roles(http: &Http, member: &Member, remove: &[RoleId], add: &[RoleId]) -> anyhow::Result<()> {
+    let mut roles: Vec<RoleId> = member.roles.iter()
+        .filter(|role_id| !remove.contains(role_id))
+        .copied()
+        .collect();
+    roles.extend(add.iter().filter(|role_id| !roles.contains(role_id)).collect::<Vec<_>>());
+
+    member.guild_id.edit_member(http, member.user.id, EditMember::new().roles(roles)).await
+        .map_err(|e| anyhow::Error::new(e).context("Could not update roles"))?;
+
+    Ok(())
+}
+
+/// Everything a dunce is going to change, worked out before anything is changed
+pub struct DuncePlan {
+    pub user: User,
+    pub reason: Option<String>,
+    pub undunce_time: DateTime<Utc>,
+    pub currently_dunced: bool,
+    dm_title: &'static str,
+    /// The user's membership, if their roles are going to be swapped for the dunce role
+    member: Option<Member>,
+    pub roles_to_remove: Vec<RoleId>,
+    pub kept_roles: Vec<RoleId>,
+}
+
+impl DuncePlan {
+    fn kept_role_mentions(&self) -> String {
+        self.kept_roles.iter()
+            .map(|role_id| role_id.mention().to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Describe the planned dunce for moderators to review
+    pub fn summary(&self) -> CreateEmbed {
+        let mut summary = CreateEmbed::new()
+            .color(Color::from_rgb(179, 38, 255))
+            .title(if self.currently_dunced { "Update dunce time?" } else { "Dunce user?" })
+            .description(format!("{} ({})", self.user.mention(), self.user.id))
+            .field("Expires", format!("<t:{}:f>", self.undunce_time.timestamp()), true)
+            .field("Reason", self.reason.clone().unwrap_or("*No reason specified*".to_string()), false);
+        if !self.roles_to_remove.is_empty() {
+            summary = summary.field("Roles to remove", self.roles_to_remove.iter()
+                .map(|role_id| role_id.mention().to_string())
+                .collect::<Vec<_>>()
+                .join(" "), false);
+        }
+        if !self.kept_roles.is_empty() {
+            summary = summary.field("Kept roles", self.kept_role_mentions(), false);
+        }
+
+        summary
+    }
+
+    /// The DM the user will receive
+    pub fn dm_embed(&self) -> CreateEmbed {
+        dm_embed(self.dm_title, self.reason.as_ref(), self.undunce_time)
+    }
+}
+
+/// Work out what dunceing a user would change
+pub async fn plan_dunce(
+    http: &Http,
+    db: &DatabaseConnection,
+    bot_id: UserId,
+    user: User,
+    expiry: DunceExpiry,
+    reason: Option<String>
+) -> anyhow::Result<DuncePlan> {
     // Check for an existing dunce
-    let currently_dunced = DunceInstants::find_by_id(user.id.get() as i64)
-        .one(&ctx.data().db).await?.is_some();
+    let current_dunce = DunceInstants::find_by_id(user.id.get() as i64)
+        .one(db).await?;
+    let currently_dunced = current_dunce.is_some();
 
-    let mut error_messages: Vec<String> = vec![];
+    // Calculate when to undunce
+    let undunce_time = expiry.resolve(current_dunce.map(|dunce| dunce.undunce_instant))?;
+    let dm_title = expiry.dm_title(currently_dunced);
 
     // Manage the user's roles if they are:
     // - Not already dunced
     // - In the server
-    if !currently_dunced && let Some(member) = P2SR_SERVER.member(ctx.serenity_context(), user.id).await.ok() {
-        let roles_to_remove: Vec<RoleId> = member.roles.clone().into_iter()
-            .filter(|role_id| *role_id != P2SR_DUNCE_ROLE)
-            .collect();
-
-        let role_active_models: Vec<luma1_data::entity::dunce_stored_roles::ActiveModel> =
-            roles_to_remove.iter().map(|role_id| {
-                luma1_data::entity::dunce_stored_roles::ActiveModel {
-                    user_id: Set(ctx.author().id.into()),
-                    role_id: Set((*role_id).into())
-                }
-            }).collect();
-
-        join_and_accumulate_errors!(error_messages,
-                // Queue recording user's roles in DB
-                DunceStoredRoles::insert_many(role_active_models)
-                .on_empty_do_nothing()
-                .exec(&ctx.data().db),
-
-                // Queue removing user's roles
-                member.remove_roles(ctx.http(), &roles_to_remove),
-
-                // Queue adding the dunce role
-                member.add_role(ctx.http(), P2SR_DUNCE_ROLE)
-            );
-    }
-
-    // Update/insert undunce time in DB and send a report in the action log
-    join_and_accumulate_errors!(error_messages,
-        // Queue inserting undunce time in DB (updating if it already exists)
+    let member = match currently_dunced {
+        true => None,
+        false => P2SR_SERVER.member(http, user.id).await.ok()
+    };
+    let (roles_to_remove, kept_roles) = match &member {
+        Some(member) => super::roles::split_removable_roles(http, bot_id, member).await?,
+        None => (vec![], vec![])
+    };
+
+    Ok(DuncePlan { user, reason, undunce_time, currently_dunced, dm_title, member, roles_to_remove, kept_roles })
+}
+
+/// Carry out a planned dunce, returning a summary for the moderator.
+/// Database changes are made in one transaction, and role changes are undone if anything fails.
+pub async fn execute_dunce(
+    http: &Http,
+    db: &DatabaseConnection,
+    moderator: &User,
+    plan: &DuncePlan
+) -> anyhow::Result<String> {
+    let DuncePlan { user, reason, undunce_time, currently_dunced, member, roles_to_remove, kept_roles, .. } = plan;
+    let (undunce_time, currently_dunced) = (*undunce_time, *currently_dunced);
+
+    // Swap the user's roles for the dunce role
+    if let Some(member) = member {
+        swap_roles(http, member, roles_to_remove, &[P2SR_DUNCE_ROLE]).await
+            .map_err(|e| e.context("Failed to dunce user, nothing was changed"))?;
+    }
+
+    // The transaction only covers the database, never requests to Discord
+    let db_result: anyhow::Result<()> = async {
+        let txn = db.begin().await?;
+
+        // Record user's roles
+        DunceStoredRoles::insert_many(roles_to_remove.iter().map(|role_id| {
+            luma1_data::entity::dunce_stored_roles::ActiveModel {
+                user_id: Set(user.id.into()),
+                role_id: Set((*role_id).into())
+            }
+        })).on_conflict(
+            sea_query::OnConflict::columns([
+                luma1_data::entity::dunce_stored_roles::Column::UserId,
+                luma1_data::entity::dunce_stored_roles::Column::RoleId
+            ]).do_nothing().to_owned()
+        ).on_empty_do_nothing().exec(&txn).await?;
+
+        // Insert undunce time (updating if it already exists)
         DunceInstants::insert(luma1_data::entity::dunce_instants::ActiveModel {
             user_id: Set(user.id.into()),
             undunce_instant: Set(undunce_time)
@@ -116,32 +382,94 @@ pub async fn dunce(
             sea_query::OnConflict::column(luma1_data::entity::dunce_instants::Column::UserId)
                 .update_column(luma1_data::entity::dunce_instants::Column::UndunceInstant)
                 .to_owned()
-        ).exec(&ctx.data().db),
-
-        // Queue mod actions notification
-        super::send_mod_action_log(ctx.http(), ctx.author().clone(), move |embed| {
-            embed.description(
-                format!("{} {} ({})",
-                    if currently_dunced { "Updated dunce time for" } else { "Dunced" },
-                    user.mention(),
-                    &user.id
-                )
+        ).exec(&txn).await?;
+
+        // Record the dunce in the user's history
+        record_dunce(&txn, user.id, moderator.id, reason.clone(), undunce_time).await?;
+
+        txn.commit().await?;
+        Ok(())
+    }.await;
+    if let Err(e) = db_result {
+        // The dunce was never recorded, so give the user their roles back
+        let mut error = e.context("Failed to dunce user, the dunce was not recorded");
+        if let Some(member) = member && let Err(e) = swap_roles(http, member, &[P2SR_DUNCE_ROLE], roles_to_remove).await {
+            error = anyhow!("{:#}\n- {:#}", error, e);
+        }
+        return Err(error);
+    }
+
+    // Send a report in the action log
+    let kept_role_mentions = plan.kept_role_mentions();
+    let log_result = super::send_mod_action_log(http, moderator.clone(), |embed| {
+        let embed = embed.description(
+            format!("{} {} ({})",
+                if currently_dunced { "Updated dunce time for" } else { "Dunced" },
+                user.mention(),
+                user.id
             )
-            .field("Remaining", format!("Undunce <t:{}:R>", undunce_time.timestamp()), true)
-            .field("Expires", format!("<t:{}:f>", undunce_time.timestamp()), true)
-        })
-    );
+        )
+        .field("Remaining", format!("Undunce <t:{}:R>", undunce_time.timestamp()), true)
+        .field("Expires", format!("<t:{}:f>", undunce_time.timestamp()), true)
+        .field("Reason", reason.clone().unwrap_or("*No reason specified*".to_string()), false);
 
-    if !error_messages.is_empty() {
-        ctx.say(format!("Failed to dunce user:```diff\n{}\n```", error_messages.join("\n"))).await?;
-    } else {
-        if currently_dunced {
-            ctx.say(format!("Updated dunce time for user {} ({}), they will be undunced <t:{}:R>", user_mention, user_id, undunce_time.timestamp())).await?;
+        if kept_roles.is_empty() {
+            embed
         } else {
-            ctx.say(format!("Dunced user {} ({}), they will be undunced <t:{}:R>", user_mention, user_id, undunce_time.timestamp())).await?;
+            embed.field("Kept roles", &kept_role_mentions, false)
         }
+    }).await;
+
+    // Try to DM the user the result
+    let dm_result = send_dm_notification(http, user, plan.dm_embed()).await;
+
+    let mut response = if currently_dunced {
+        format!("Updated dunce time for user {} ({}), they will be undunced <t:{}:R>", user.mention(), user.id, undunce_time.timestamp())
+    } else {
+        format!("Dunced user {} ({}), they will be undunced <t:{}:R>", user.mention(), user.id, undunce_time.timestamp())
+    };
+    if !kept_roles.is_empty() {
+        response.push_str(&format!("\nKept roles that can't be removed: {}", kept_role_mentions));
+    }
+    if let Err(e) = log_result {
+        response.push_str(&format!("\n```diff\n- {:#}\n```", e));
+    }
+    if dm_result.is_err() {
+        response.push_str("\n```diff\n- Unable to DM User\n```");
+    }
+    if let Some(warning) = super::alts::linked_accounts_warning(db, user.id).await {
+        response.push_str(&format!("\n{}", warning));
+    }
+
+    Ok(response)
+}
+
+/// Dunce a user, or change the expiry of an existing dunce
+pub async fn apply_dunce(
+    ctx: Context<'_>,
+    user: User,
+    expiry: DunceExpiry,
+    reason: Option<String>,
+    dry_run: bool
+) -> anyhow::Result<()> {
+    super::checks::ensure_can_moderate(ctx, &user).await?;
+
+    let plan = plan_dunce(ctx.http(), &ctx.data().db, ctx.framework().bot_id, user, expiry, reason).await?;
+
+    let mut summary = plan.summary();
+    if let Some(warning) = super::alts::linked_accounts_warning(&ctx.data().db, plan.user.id).await {
+        summary = summary.field("Alts", warning, false);
+    }
+    if dry_run {
+        return super::confirm::show_dry_run(ctx, summary, Some(plan.dm_embed()), vec![]).await;
+    }
+    if !super::confirm::confirm(ctx, "dunce", summary).await? {
+        return Ok(());
     }
 
+    let response = execute_dunce(ctx.http(), &ctx.data().db, ctx.author(), &plan).await?;
+    ctx.say(response).await?;
+
     Ok(())
 }
 
@@ -155,12 +483,14 @@ pub async fn undunce(
     ctx: Context<'_>,
     #[description = "User to undunce"] user: User
 ) -> anyhow::Result<()> {
+    super::checks::ensure_can_moderate(ctx, &user).await?;
+
     // Check whether the user is dunced
     let dunce_instant = match DunceInstants::find_by_id(user.id.get() as i64)
         .one(&ctx.data().db).await? {
         Some(i) => i,
         None => {
-            ctx.say("```diff\n - User {} ({}) is not dunced.\n```").await?;
+            ctx.say(format!("```diff\n- User {} ({}) is not dunced.\n```", user.mention(), user.id)).await?;
             return Ok(());
         }
     };
@@ -168,47 +498,62 @@ pub async fn undunce(
     let user_mention = user.mention();
     let user_id = user.id;
 
-    let mut error_messages: Vec<String> = vec![];
+    // Collect roles the user had before being dunced
+    let stored_roles : Vec<RoleId> = DunceStoredRoles::find()
+        .filter(luma1_data::entity::dunce_stored_roles::Column::UserId.eq(user.id.get() as i64))
+        .all(&ctx.data().db).await?
+        .iter().map(|r| RoleId::new(r.role_id as u64))
+        .collect();
 
     // We can only manage roles if the user is in the server
-    if let Some(member) = P2SR_SERVER.member(ctx.serenity_context(), user.id).await.ok() {
-        // Collect roles the user had before being dunced
-        let stored_roles : Vec<RoleId> = DunceStoredRoles::find()
-            .filter(luma1_data::entity::dunce_stored_roles::Column::UserId.eq(user.id.get() as i64))
-            .all(&ctx.data().db).await?
-            .iter().map(|r| RoleId::new(r.role_id as u64))
-            .collect();
-
-        join_and_accumulate_errors!(error_messages,
-            // Add roles the user previously had
-            member.add_roles(ctx.http(), &stored_roles),
+    let member = P2SR_SERVER.member(ctx.serenity_context(), user.id).await.ok();
 
-            // Remove dunce role
-            member.remove_role(ctx.http(), P2SR_DUNCE_ROLE)
-        );
+    // Swap the dunce role for the roles the user previously had
+    if let Some(member) = &member && let Err(e) = swap_roles(ctx.http(), member, &[P2SR_DUNCE_ROLE], &stored_roles).await {
+        ctx.say(format!("Failed to undunce user, they are still dunced:```diff\n- {:#}\n```", e)).await?;
+        return Ok(());
     }
 
-    join_and_accumulate_errors!(error_messages,
-        // Queue notifying mod-actions
-        super::send_mod_action_log(ctx.http(), ctx.author().clone(), move |embed| {
-            embed.description(format!("Undunced user {} ({})", user_mention, user_id))
-        }),
+    // The transaction only covers the database, never requests to Discord
+    let db_result: anyhow::Result<()> = async {
+        let txn = ctx.data().db.begin().await?;
 
         // Clear dunce instant
         DunceInstants::delete(dunce_instant.into_active_model())
-            .exec(&ctx.data().db),
+            .exec(&txn).await?;
 
         // Clear stored roles
         DunceStoredRoles::delete_many()
             .filter(luma1_data::entity::dunce_stored_roles::Column::UserId.eq(user.id.get() as i64))
-            .exec(&ctx.data().db)
-    );
+            .exec(&txn).await?;
+
+        // Close the dunce in the user's history
+        end_dunce_records(&txn, user.id, ctx.author().id).await?;
+
+        txn.commit().await?;
+        Ok(())
+    }.await;
+    if let Err(e) = db_result {
+        // The undunce was never recorded, so put the user back in the dunce role
+        let mut response = format!("Failed to undunce user, they are still dunced:```diff\n- {:#}", e);
+        if let Some(member) = &member && let Err(e) = swap_roles(ctx.http(), member, &stored_roles, &[P2SR_DUNCE_ROLE]).await {
+            response.push_str(&format!("\n- {:#}", e));
+        }
+        response.push_str("\n```");
+        ctx.say(response).await?;
+        return Ok(());
+    }
 
-    if !error_messages.is_empty() {
-        ctx.say(format!("Failed to undunce user:```diff\n{}\n```", error_messages.join("\n"))).await?;
+    // Notify mod-actions
+    let log_result = super::send_mod_action_log(ctx.http(), ctx.author().clone(), |embed| {
+        embed.description(format!("Undunced user {} ({})", user_mention, user_id))
+    }).await;
+
+    if let Err(e) = log_result {
+        ctx.say(format!("Undunced user {} ({})\n```diff\n- {:#}\n```", user.mention(), user.id, e)).await?;
     } else {
-        ctx.say(format!("Undunced user {} ({})", user.mention(), &user.id)).await?;
+        ctx.say(format!("Undunced user {} ({})", user.mention(), user.id)).await?;
     }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use poise::futures_util::StreamExt;
+use poise::serenity_prelude::{Http, Member, Mentionable, UserId};
+
+use luma1_data::entity::prelude::*;
+use luma1_data::sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+
+use crate::context::{Context, P2SR_DUNCE_ROLE, P2SR_SERVER};
+use crate::entity::prelude::*;
+
+/// Number of dunced users listed on each page
+const ENTRIES_PER_PAGE: usize = 10;
+
+/// Fetch every member of the server
+pub async fn fetch_members(http: &Http) -> anyhow::Result<HashMap<UserId, Member>> {
+    let mut members = HashMap::new();
+
+    let mut member_stream = P2SR_SERVER.members_iter(http).boxed();
+    while let Some(member) = member_stream.next().await {
+        let member = member?;
+        members.insert(member.user.id, member);
+    }
+
+    Ok(members)
+}
+
+/// List everyone who is currently dunced
+#[poise::command(
+slash_command,
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn dunced(ctx: Context<'_>) -> anyhow::Result<()> {
+    ctx.defer().await?;
+
+    let dunce_instants = DunceInstants::find()
+        .order_by_asc(luma1_data::entity::dunce_instants::Column::UndunceInstant)
+        .all(&ctx.data().db).await?;
+
+    // Active history records know who dunced each user
+    let moderators: HashMap<i64, i64> = DunceHistory::find()
+        .filter(crate::entity::dunce_history::Column::EndedAt.is_null())
+        .all(&ctx.data().db).await?
+        .into_iter().map(|record| (record.user_id, record.moderator_id))
+        .collect();
+
+    let members = fetch_members(ctx.http()).await?;
+
+    let entries: Vec<String> = dunce_instants.iter().map(|dunce_instant| {
+        let user_id = UserId::new(dunce_instant.user_id as u64);
+        let mut entry = format!("{} ({}) - undunce <t:{}:R>",
+            user_id.mention(), user_id, dunce_instant.undunce_instant.timestamp());
+
+        if let Some(moderator_id) = moderators.get(&dunce_instant.user_id) {
+            entry.push_str(&format!(" - by <@{}>", moderator_id));
+        }
+
+        match members.get(&user_id) {
+            Some(member) if !member.roles.contains(&P2SR_DUNCE_ROLE) => entry.push_str(" - ⚠️ missing dunce role"),
+            Some(_) => {}
+            None => entry.push_str(" - not in server")
+        }
+
+        entry
+    }).collect();
+
+    // Members with the dunce role that nothing will ever undunce
+    let unrecorded: Vec<String> = members.values()
+        .filter(|member| member.roles.contains(&P2SR_DUNCE_ROLE)
+            && !dunce_instants.iter().any(|dunce_instant| dunce_instant.user_id == member.user.id.get() as i64))
+        .map(|member| format!("{} ({})", member.mention(), member.user.id))
+        .collect();
+
+    let mut pages: Vec<String> = entries.chunks(ENTRIES_PER_PAGE)
+        .map(|chunk| format!("**Currently dunced ({})**\n{}", entries.len(), chunk.join("\n")))
+        .collect();
+    if pages.is_empty() {
+        pages.push("**Nobody is currently dunced**".to_string());
+    }
+    pages.extend(unrecorded.chunks(ENTRIES_PER_PAGE)
+        .map(|chunk| format!("**⚠️ Dunce role without a dunce time ({})**\n{}", unrecorded.len(), chunk.join("\n"))));
+
+    let pages: Vec<&str> = pages.iter().map(|page| page.as_str()).collect();
+    poise::builtins::paginate(ctx, &pages).await?;
+
+    Ok(())
+}
@@ -1,14 +1,47 @@
+mod alts;
 mod ban;
+mod cases;
+mod checks;
+mod confirm;
+mod context_menus;
 mod dunce;
+mod dunced;
 mod cleanup;
+mod modmail;
+mod notes;
+mod purgeuser;
+mod reconcile;
+mod report;
+mod roles;
+mod slowmode;
+mod transcript;
+mod warn;
 
 pub use ban::ban as ban;
+pub use ban::end_ban_records;
+pub use ban::routes as appeal_routes;
 pub use dunce::dunce as dunce;
 pub use dunce::undunce as undunce;
+pub use dunce::{end_dunce_records, execute_dunce, plan_dunce, swap_roles, DunceExpiry};
+pub use dunced::dunced as dunced;
 pub use cleanup::cleanup as cleanup;
+pub use modmail::{close, modmail, reply};
+pub use purgeuser::purgeuser as purgeuser;
+pub use slowmode::slowmode as slowmode;
+pub use slowmode::revert_expired as revert_expired_slowmodes;
+pub use reconcile::reconcile_on_startup as reconcile_dunces_on_startup;
+pub use report::{report, report_message};
+pub use report::routes as report_routes;
+pub use warn::{warn, warn_user};
+pub use checks::check_can_moderate;
+pub use alts::{alt, join_within, linked_accounts};
+pub use cases::cases;
+pub use context_menus::{ban_menu, cases_menu, cleanup_after_menu, dunce_day_menu, dunce_hour_menu, warn_menu};
+pub use notes::{note, summarize_notes, user_notes};
+pub use roles::highest_position;
 use crate::context::{P2SR_NOTIFICATIONS_CHANNEL};
 
-use poise::serenity_prelude::{CreateEmbed, CreateEmbedAuthor, CreateMessage};
+use poise::serenity_prelude::{CreateAttachment, CreateEmbed, CreateEmbedAuthor, CreateMessage};
 use serenity::all::{CacheHttp, Timestamp, User};
 
 pub async fn send_mod_action_log(
@@ -16,6 +49,15 @@ pub async fn send_mod_action_log(
     author: User,
     embed_builder: impl Fn(CreateEmbed) -> CreateEmbed
 ) -> anyhow::Result<()> {
+    send_mod_action_log_with_files(http, author, vec![], embed_builder).await
+}
+
+pub async fn send_mod_action_log_with_files(
+    http: impl CacheHttp,
+    author: User,
+    files: Vec<CreateAttachment>,
+    embed_builder: impl Fn(CreateEmbed) -> CreateEmbed
+) -> anyhow::Result<()> {
 
     let mut notif_author = CreateEmbedAuthor::new("");
     if let Some(url) = author.avatar_url() {
@@ -27,7 +69,7 @@ pub async fn send_mod_action_log(
     let embed = embed_builder(embed);
 
     P2SR_NOTIFICATIONS_CHANNEL.send_message(
-        http, CreateMessage::new().embed(embed)
+        http, CreateMessage::new().embed(embed).add_files(files)
     ).await.map_err(|e| anyhow::Error::new(e).context("Could not send notification message"))?;
 
     Ok(())
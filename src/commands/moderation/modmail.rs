@@ -0,0 +1,211 @@
+use anyhow::bail;
+use chrono::Utc;
+use poise::futures_util::TryStreamExt;
+use poise::serenity_prelude::{ChannelId, Color, CreateEmbed, CreateEmbedAuthor, CreateEmbedFooter, CreateMessage, EditThread, Mentionable, Message, Timestamp, User, UserId};
+
+use luma1_data::sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, ModelTrait, QueryFilter, QueryOrder, Set, sea_query};
+
+use crate::context::Context;
+use crate::entity::{modmail_blocks, modmail_threads};
+use crate::entity::prelude::*;
+
+/// Find the open modmail conversation relayed to a channel
+async fn find_open_thread(ctx: Context<'_>, channel_id: ChannelId) -> anyhow::Result<modmail_threads::Model> {
+    let thread = ModmailThreads::find()
+        .filter(modmail_threads::Column::ThreadId.eq(channel_id.get() as i64))
+        .filter(modmail_threads::Column::ClosedAt.is_null())
+        .one(&ctx.data().db).await?;
+
+    match thread {
+        Some(thread) => Ok(thread),
+        None => bail!("This channel isn't an open modmail thread")
+    }
+}
+
+/// Reply to the user of a modmail thread
+#[poise::command(
+slash_command,
+guild_only,
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn reply(
+    ctx: Context<'_>,
+    #[description = "Message to send to the user"] #[max_length = 1800] message: String,
+    #[description = "Hide your name from the user (default: false)"] anonymous: Option<bool>
+) -> anyhow::Result<()> {
+    let thread = find_open_thread(ctx, ctx.channel_id()).await?;
+    let anonymous = anonymous.unwrap_or(false);
+
+    let sender = match anonymous {
+        true => "Moderators".to_string(),
+        false => format!("{} (moderator)", ctx.author().name)
+    };
+    let embed = CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .author(CreateEmbedAuthor::new(sender))
+        .description(&message)
+        .footer(CreateEmbedFooter::new("Portal 2 Speedrun Server"))
+        .timestamp(Timestamp::now());
+
+    let user = UserId::new(thread.user_id as u64).to_user(ctx).await?;
+    let channel = user.create_dm_channel(ctx).await?;
+    channel.send_message(ctx, CreateMessage::new().embed(embed)).await
+        .map_err(|e| anyhow::Error::new(e).context("Could not DM the user"))?;
+
+    ctx.say(format!("**Reply from {}{}:** {}",
+        ctx.author().name, if anonymous { " (anonymous)" } else { "" }, message)).await?;
+
+    Ok(())
+}
+
+/// Close a modmail thread and archive its transcript
+#[poise::command(
+slash_command,
+guild_only,
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn close(
+    ctx: Context<'_>,
+    #[description = "Reason to record"] reason: Option<String>
+) -> anyhow::Result<()> {
+    let thread = find_open_thread(ctx, ctx.channel_id()).await?;
+    ctx.defer().await?;
+
+    let messages: Vec<Message> = super::cleanup::message_history(ctx.http(), ctx.channel_id(), None)
+        .try_collect().await?;
+    let user_id = UserId::new(thread.user_id as u64);
+
+    let mut record = thread.into_active_model();
+    record.closed_at = Set(Some(Utc::now()));
+    record.closed_by = Set(Some(ctx.author().id.get() as i64));
+    record.update(&ctx.data().db).await?;
+
+    super::send_mod_action_log_with_files(
+        ctx.http(),
+        ctx.author().clone(),
+        vec![super::transcript::transcript_attachment(super::transcript::build_transcript(ctx.channel_id(), &messages))],
+        |embed| embed
+            .description(format!("Closed modmail from {} ({}) in {}", user_id.mention(), user_id, ctx.channel_id().mention()))
+            .field("Reason", reason.clone().unwrap_or("*No reason specified*".to_string()), false)
+    ).await?;
+
+    // The user may have left or closed their DMs, the thread is closed either way
+    let dm_result = async {
+        let user = user_id.to_user(ctx).await?;
+        user.create_dm_channel(ctx).await?
+            .say(ctx, "Your conversation with the moderators was closed. Send another message if you need anything else").await?;
+        Ok::<_, anyhow::Error>(())
+    }.await;
+
+    if dm_result.is_ok() {
+        ctx.say("Closed this thread, the transcript was saved to the mod log").await?;
+    } else {
+        ctx.say("Closed this thread, the transcript was saved to the mod log\n```diff\n- Unable to DM User\n```").await?;
+    }
+
+    ctx.channel_id().edit_thread(ctx, EditThread::new().archived(true).locked(true)).await?;
+
+    Ok(())
+}
+
+/// Manage who can use modmail
+#[poise::command(
+slash_command,
+required_permissions = "MODERATE_MEMBERS",
+subcommands("modmail_block", "modmail_unblock", "modmail_blocked"),
+subcommand_required
+)]
+pub async fn modmail(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Stop relaying a user's DMs to the staff
+#[poise::command(
+slash_command,
+rename = "block",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn modmail_block(
+    ctx: Context<'_>,
+    #[description = "User to block"] user: User,
+    #[description = "Reason to record"] reason: Option<String>
+) -> anyhow::Result<()> {
+    ModmailBlocks::insert(modmail_blocks::ActiveModel {
+        user_id: Set(user.id.get() as i64),
+        blocked_by: Set(ctx.author().id.get() as i64),
+        blocked_at: Set(Utc::now()),
+        reason: Set(reason.clone())
+    }).on_conflict(
+        sea_query::OnConflict::column(modmail_blocks::Column::UserId)
+            .update_columns([modmail_blocks::Column::BlockedBy, modmail_blocks::Column::BlockedAt, modmail_blocks::Column::Reason])
+            .to_owned()
+    ).exec(&ctx.data().db).await?;
+
+    super::send_mod_action_log(ctx.http(), ctx.author().clone(), |embed| {
+        embed.description(format!("Blocked {} ({}) from modmail", user.mention(), user.id))
+            .field("Reason", reason.clone().unwrap_or("*No reason specified*".to_string()), false)
+    }).await?;
+
+    ctx.say(format!("Blocked {} ({}) from modmail, their DMs will be ignored", user.mention(), user.id)).await?;
+
+    Ok(())
+}
+
+/// Relay a blocked user's DMs to the staff again
+#[poise::command(
+slash_command,
+rename = "unblock",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn modmail_unblock(
+    ctx: Context<'_>,
+    #[description = "User to unblock"] user: User
+) -> anyhow::Result<()> {
+    let Some(block) = ModmailBlocks::find_by_id(user.id.get() as i64).one(&ctx.data().db).await? else {
+        ctx.say(format!("```diff\n- User {} ({}) is not blocked from modmail.\n```", user.mention(), user.id)).await?;
+        return Ok(());
+    };
+    block.delete(&ctx.data().db).await?;
+
+    super::send_mod_action_log(ctx.http(), ctx.author().clone(), |embed| {
+        embed.description(format!("Unblocked {} ({}) from modmail", user.mention(), user.id))
+    }).await?;
+
+    ctx.say(format!("Unblocked {} ({}) from modmail", user.mention(), user.id)).await?;
+
+    Ok(())
+}
+
+/// List users blocked from modmail
+#[poise::command(
+slash_command,
+rename = "blocked",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn modmail_blocked(ctx: Context<'_>) -> anyhow::Result<()> {
+    let blocks = ModmailBlocks::find()
+        .order_by_asc(modmail_blocks::Column::BlockedAt)
+        .all(&ctx.data().db).await?;
+    if blocks.is_empty() {
+        ctx.say("Nobody is blocked from modmail").await?;
+        return Ok(());
+    }
+
+    let entries: Vec<String> = blocks.iter().map(|block| {
+        format!("<@{}> ({}) - by <@{}> <t:{}:R>{}",
+            block.user_id, block.user_id, block.blocked_by, block.blocked_at.timestamp(),
+            block.reason.as_ref().map(|reason| format!(" - {}", reason)).unwrap_or_default())
+    }).collect();
+    let pages: Vec<String> = entries.chunks(10)
+        .map(|chunk| format!("**Blocked from modmail ({})**\n{}", entries.len(), chunk.join("\n")))
+        .collect();
+    let pages: Vec<&str> = pages.iter().map(|page| page.as_str()).collect();
+    poise::builtins::paginate(ctx, &pages).await?;
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use chrono::Utc;
+use poise::serenity_prelude::{Mentionable, User, UserId};
+
+use luma1_data::sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, ModelTrait, QueryFilter, QueryOrder, Set};
+
+use crate::context::Context;
+use crate::entity::notes;
+use crate::entity::prelude::*;
+
+/// Every note kept about a user, oldest first
+pub async fn user_notes(db: &impl ConnectionTrait, user_id: UserId) -> anyhow::Result<Vec<notes::Model>> {
+    Ok(Notes::find()
+        .filter(notes::Column::UserId.eq(user_id.get() as i64))
+        .order_by_asc(notes::Column::CreatedAt)
+        .all(db).await?)
+}
+
+/// Format a note for staff to read
+pub fn format_note(note: &notes::Model) -> String {
+    format!("`#{}` <t:{}:d> by <@{}>: {}", note.id, note.created_at.timestamp(), note.author_id, note.content)
+}
+
+/// Most notes listed in a summary
+const SUMMARY_NOTES: usize = 5;
+/// Longest a note can be in a summary before it's cut off
+const SUMMARY_NOTE_LENGTH: usize = 150;
+/// Longest a whole summary can be, the limit of an embed field
+const SUMMARY_LENGTH: usize = 1024;
+
+/// Summarise the most recent notes about a user, short enough for an embed field
+pub fn summarize_notes(notes: &[notes::Model]) -> String {
+    let mut summary = String::new();
+    for (shown, note) in notes.iter().rev().enumerate() {
+        let mut note = note.clone();
+        if note.content.chars().count() > SUMMARY_NOTE_LENGTH {
+            note.content = format!("{}...", note.content.chars().take(SUMMARY_NOTE_LENGTH).collect::<String>());
+        }
+        let line = format!("{}\n", format_note(&note));
+
+        // Leave room for the line saying how many notes were left out
+        let more = format!("...and {} older, see `/note list`", notes.len() - shown);
+        if shown == SUMMARY_NOTES || summary.len() + line.len() + more.len() > SUMMARY_LENGTH {
+            summary.push_str(&more);
+            break;
+        }
+        summary.push_str(&line);
+    }
+
+    summary.trim_end().to_string()
+}
+
+/// Keep private notes about users
+#[poise::command(
+slash_command,
+required_permissions = "MODERATE_MEMBERS",
+subcommands("note_add", "note_list", "note_remove"),
+subcommand_required
+)]
+pub async fn note(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Add a private note about a user
+#[poise::command(
+slash_command,
+rename = "add",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn note_add(
+    ctx: Context<'_>,
+    #[description = "User the note is about"] user: User,
+    #[description = "Note, only ever shown to staff"] #[max_length = 1000] content: String
+) -> anyhow::Result<()> {
+    let note = notes::ActiveModel {
+        user_id: Set(user.id.get() as i64),
+        author_id: Set(ctx.author().id.get() as i64),
+        content: Set(content),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }.insert(&ctx.data().db).await?;
+
+    ctx.say(format!("Added note `#{}` to {} ({})", note.id, user.mention(), user.id)).await?;
+
+    Ok(())
+}
+
+/// List the private notes about a user
+#[poise::command(
+slash_command,
+rename = "list",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn note_list(
+    ctx: Context<'_>,
+    #[description = "User to list notes for"] user: User
+) -> anyhow::Result<()> {
+    let notes = user_notes(&ctx.data().db, user.id).await?;
+    if notes.is_empty() {
+        ctx.say(format!("No notes about {} ({})", user.mention(), user.id)).await?;
+        return Ok(());
+    }
+
+    let entries: Vec<String> = notes.iter().map(format_note).collect();
+    let pages: Vec<String> = entries.chunks(10)
+        .map(|chunk| format!("**Notes about {} ({})**\n{}", user.mention(), entries.len(), chunk.join("\n")))
+        .collect();
+    let pages: Vec<&str> = pages.iter().map(|page| page.as_str()).collect();
+    poise::builtins::paginate(ctx, &pages).await?;
+
+    Ok(())
+}
+
+/// Remove a private note about a user
+#[poise::command(
+slash_command,
+rename = "remove",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn note_remove(
+    ctx: Context<'_>,
+    #[description = "User the note is about"] user: User,
+    #[description = "Number of the note, shown by /note list"] id: i32
+) -> anyhow::Result<()> {
+    let note = Notes::find_by_id(id).one(&ctx.data().db).await?
+        .filter(|note| note.user_id == user.id.get() as i64);
+    let Some(note) = note else {
+        ctx.say(format!("```diff\n- There is no note #{} about {} ({}).\n```", id, user.mention(), user.id)).await?;
+        return Ok(());
+    };
+    note.delete(&ctx.data().db).await?;
+
+    ctx.say(format!("Removed note `#{}` from {} ({})", id, user.mention(), user.id)).await?;
+
+    Ok(())
+}
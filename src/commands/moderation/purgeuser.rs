@@ -0,0 +1,260 @@
+use std::time::Instant;
+
+use anyhow::anyhow;
+use chrono::{DateTime, TimeDelta, Utc};
+use poise::futures_util::{FutureExt, StreamExt};
+use poise::serenity_prelude::{ChannelId, ChannelType, Color, ComponentInteractionCollector, CreateEmbed, CreateInteractionResponse, CreateMessage, GuildChannel, Http, Mentionable, Message, User};
+
+use crate::context::Context;
+use super::cleanup::{delete_batch, deletion_batches, message_history, progress_reply, show_progress, MessageFilter, PROGRESS_INTERVAL};
+
+/// Whether a channel of this kind can hold messages
+fn has_messages(kind: ChannelType) -> bool {
+    matches!(
+        kind,
+        ChannelType::Text | ChannelType::News | ChannelType::Voice | ChannelType::Stage
+            | ChannelType::PublicThread | ChannelType::PrivateThread | ChannelType::NewsThread
+    )
+}
+
+/// Whether a channel of this kind can have public threads
+fn has_threads(kind: ChannelType) -> bool {
+    matches!(kind, ChannelType::Text | ChannelType::News | ChannelType::Forum)
+}
+
+/// Public threads of a channel that were archived since the cutoff, so may hold recent messages.
+/// Only the 100 most recently archived threads are looked at, older ones are skipped.
+async fn archived_threads_since(http: &Http, channel_id: ChannelId, cutoff: DateTime<Utc>) -> anyhow::Result<Vec<GuildChannel>> {
+    let threads = channel_id.get_archived_public_threads(http, None, Some(100)).await?.threads;
+
+    Ok(threads.into_iter()
+        .filter(|thread| thread.thread_metadata
+            .and_then(|metadata| metadata.archive_timestamp)
+            .is_some_and(|archived_at| *archived_at >= cutoff))
+        .collect())
+}
+
+/// List a message count for each channel, cut short to fit an embed field
+fn summarize_channels(counts: &[(ChannelId, usize)]) -> String {
+    let mut summary = String::new();
+    for (i, (channel_id, count)) in counts.iter().enumerate() {
+        let line = format!("{}: {}\n", channel_id.mention(), count);
+        if summary.len() + line.len() > 1000 {
+            summary.push_str(&format!("...and {} more", counts.len() - i));
+            break;
+        }
+        summary.push_str(&line);
+    }
+
+    summary
+}
+
+/// Delete a user's recent messages from every channel
+#[poise::command(
+slash_command,
+guild_only,
+required_permissions = "MANAGE_MESSAGES",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn purgeuser(
+    ctx: Context<'_>,
+    #[description = "User whose messages to delete"] user: User,
+    #[description = "Delete messages from the last X hours"] #[min = 1] #[max = 720] hours: u32,
+    #[description = "Show which messages would be deleted without deleting them"] dry_run: Option<bool>
+) -> anyhow::Result<()> {
+    ctx.defer_ephemeral().await?;
+
+    super::checks::ensure_can_moderate(ctx, &user).await?;
+
+    let guild_id = ctx.guild_id()
+        .ok_or(anyhow!("Messages can only be purged in a server"))?;
+    let cutoff = Utc::now() - TimeDelta::hours(hours as i64);
+
+    // Collect every channel and thread that has seen a message since the cutoff
+    let mut channels: Vec<GuildChannel> = guild_id.channels(ctx.http()).await?
+        .into_values().collect();
+    let mut unreadable_channels = 0;
+    let mut archived_threads = vec![];
+    for channel in channels.iter().filter(|channel| has_threads(channel.kind)) {
+        match archived_threads_since(ctx.http(), channel.id, cutoff).await {
+            Ok(threads) => archived_threads.extend(threads),
+            Err(_) => unreadable_channels += 1
+        }
+    }
+    channels.extend(archived_threads);
+    channels.extend(guild_id.get_active_threads(ctx.http()).await?.threads);
+    channels.retain(|channel| has_messages(channel.kind)
+        && channel.last_message_id.is_some_and(|id| *id.created_at() >= cutoff));
+
+    let filter = MessageFilter {
+        author: Some(user.id),
+        ..Default::default()
+    };
+
+    let cancel_id = format!("{}-purgeuser-cancel", ctx.id());
+    let progress = ctx.send(progress_reply(
+        format!("Scanning {} channels...", channels.len()), Some(&cancel_id))).await?;
+    let mut cancel_stream = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .custom_ids(vec![cancel_id.clone()])
+        .stream()
+        .boxed();
+    let mut last_progress_update = Instant::now();
+
+    // Collect the user's messages in every channel, stopping at the cutoff
+    let total_channels = channels.len();
+    let mut matched: Vec<(GuildChannel, Vec<Message>)> = vec![];
+    for (channels_scanned, channel) in channels.into_iter().enumerate() {
+        let mut messages = vec![];
+        let mut message_stream = message_history(ctx.http(), channel.id, None).boxed();
+        loop {
+            if let Some(Some(interaction)) = cancel_stream.next().now_or_never() {
+                let _ = interaction.create_response(ctx, CreateInteractionResponse::Acknowledge).await;
+                show_progress(ctx, &progress, format!("Cancelled, nothing was deleted (scanned {} of {} channels)",
+                    channels_scanned, total_channels), None).await;
+                return Ok(());
+            }
+            let Some(message) = message_stream.next().await else {
+                break;
+            };
+            let message = match message {
+                Ok(message) => message,
+                // Channels the bot can't read are skipped rather than failing the whole purge
+                Err(_) => {
+                    unreadable_channels += 1;
+                    messages.clear();
+                    break;
+                }
+            };
+            if *message.timestamp < cutoff {
+                break;
+            }
+            if filter.matches(&message) {
+                messages.push(message);
+            }
+
+            if last_progress_update.elapsed() >= PROGRESS_INTERVAL {
+                let total_matched = matched.iter().map(|(_, messages)| messages.len()).sum::<usize>() + messages.len();
+                show_progress(ctx, &progress, format!("Scanning channels... scanned {}/{}, matched {}",
+                    channels_scanned, total_channels, total_matched), Some(&cancel_id)).await;
+                last_progress_update = Instant::now();
+            }
+        }
+
+        if !messages.is_empty() {
+            matched.push((channel, messages));
+        }
+    }
+
+    let total_matched: usize = matched.iter().map(|(_, messages)| messages.len()).sum();
+    if total_matched == 0 {
+        show_progress(ctx, &progress,
+            format!("No messages from {} in the last {} hours", user.mention(), hours), None).await;
+        return Ok(());
+    }
+
+    // Keep a record of what is about to be removed before anything is deleted
+    let transcript = matched.iter()
+        .map(|(channel, messages)| super::transcript::build_transcript(channel.id, messages))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let matched_counts = summarize_channels(&matched.iter()
+        .map(|(channel, messages)| (channel.id, messages.len()))
+        .collect::<Vec<_>>());
+
+    show_progress(ctx, &progress, format!("Found {} messages from {} in {} channels",
+        total_matched, user.mention(), matched.len()), None).await;
+    let summary = CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .title("Purge messages?")
+        .description(format!("Delete {} messages from {} ({}) sent in the last {} hours",
+            total_matched, user.mention(), user.id, hours))
+        .field("Channels", &matched_counts, false);
+    if dry_run.unwrap_or(false) {
+        return super::confirm::show_dry_run(ctx, summary, None,
+            vec![super::transcript::transcript_attachment(transcript)]).await;
+    }
+    if !super::confirm::confirm(ctx, "purgeuser", summary).await? {
+        show_progress(ctx, &progress, "Cancelled, nothing was deleted".to_string(), None).await;
+        return Ok(());
+    }
+    show_progress(ctx, &progress, "Deleting messages...".to_string(), Some(&cancel_id)).await;
+
+    super::send_mod_action_log_with_files(
+        ctx.http(),
+        ctx.author().clone(),
+        vec![super::transcript::transcript_attachment(transcript)],
+        |embed| embed
+            .description(format!("Purged {} messages from {} ({}) sent in the last {} hours",
+                total_matched, user.mention(), user.id, hours))
+            .field("Channels", &matched_counts, false)
+    ).await?;
+
+    let mut total_deleted = 0;
+    let mut deleted_counts: Vec<(ChannelId, usize)> = vec![];
+    let mut error_messages: Vec<String> = vec![];
+    let mut cancelled = false;
+    for (channel, messages) in &matched {
+        let mut deleted = 0;
+        for batch in deletion_batches(messages) {
+            // Only stop between batches, so we always know which messages were deleted
+            if let Some(Some(interaction)) = cancel_stream.next().now_or_never() {
+                let _ = interaction.create_response(ctx, CreateInteractionResponse::Acknowledge).await;
+                cancelled = true;
+                break;
+            }
+
+            match delete_batch(ctx.http(), channel.id, &batch).await {
+                Ok(()) => deleted += batch.len(),
+                Err(e) => {
+                    error_messages.push(format!("- {}: {:#}", channel.name, e));
+                    break;
+                }
+            }
+
+            if last_progress_update.elapsed() >= PROGRESS_INTERVAL {
+                show_progress(ctx, &progress, format!("Deleting messages... deleted {} of {}",
+                    total_deleted + deleted, total_matched), Some(&cancel_id)).await;
+                last_progress_update = Instant::now();
+            }
+        }
+
+        total_deleted += deleted;
+        if deleted > 0 {
+            deleted_counts.push((channel.id, deleted));
+        }
+        if cancelled {
+            break;
+        }
+    }
+    error_messages.truncate(10);
+
+    if cancelled {
+        super::send_mod_action_log(ctx.http(), ctx.author().clone(), |embed| {
+            embed.description(format!("Cancelled purge of {} ({}) after deleting {} of {} messages",
+                user.mention(), user.id, total_deleted, total_matched))
+        }).await?;
+    }
+
+    let deleted_summary = summarize_channels(&deleted_counts);
+    let mut response = match total_deleted == total_matched {
+        true => format!("Removed {} messages from {}:\n{}", total_deleted, user.mention(), deleted_summary),
+        false => format!("Removed {} of {} messages from {}:\n{}", total_deleted, total_matched, user.mention(), deleted_summary)
+    };
+    if cancelled {
+        response.push_str("\nCancelled before every message was deleted");
+    }
+    if unreadable_channels > 0 {
+        response.push_str(&format!("\nSkipped {} channels that couldn't be read", unreadable_channels));
+    }
+    if !error_messages.is_empty() {
+        response.push_str(&format!("\nFailed to delete some messages:```diff\n{}\n```", error_messages.join("\n")));
+    }
+    // The interaction token may have expired on a long purge, so fall back to a DM
+    if !show_progress(ctx, &progress, response.clone(), None).await {
+        ctx.author().dm(ctx, CreateMessage::new().content(response)).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,247 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use poise::serenity_prelude::{Color, CreateEmbed, Http, Member, Mentionable, RoleId, UserId};
+
+use luma1_data::entity::prelude::*;
+use luma1_data::sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, sea_query};
+
+use crate::context::{Context, P2SR_DUNCE_ROLE, P2SR_SERVER};
+use crate::entity::prelude::*;
+
+async fn dunced_users(db: &DatabaseConnection) -> anyhow::Result<HashSet<i64>> {
+    Ok(DunceInstants::find().all(db).await?
+        .into_iter().map(|dunce_instant| dunce_instant.user_id)
+        .collect())
+}
+
+/// Members with the dunce role but no dunce time, who would never be undunced
+fn unrecorded_dunces<'a>(members: &'a HashMap<UserId, Member>, dunced_users: &HashSet<i64>) -> Vec<&'a Member> {
+    members.values()
+        .filter(|member| member.roles.contains(&P2SR_DUNCE_ROLE)
+            && !dunced_users.contains(&(member.user.id.get() as i64)))
+        .collect()
+}
+
+/// How much of what [`reconcile_dunces`] finds it is allowed to fix
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ReconcileMode {
+    /// Change nothing, only describe what doesn't line up
+    Report,
+    /// Fix everything, except members with the dunce role but no dunce time who are only reported
+    KeepUnrecorded,
+    /// Fix everything, including taking the dunce role from members without a dunce time
+    Fix,
+}
+
+/// Bring the dunce tables and the server's roles back in line with each other, as far as the mode allows.
+/// Returns a description of everything that was changed or found.
+pub async fn reconcile_dunces(
+    http: &Http,
+    db: &DatabaseConnection,
+    members: &HashMap<UserId, Member>,
+    mode: ReconcileMode
+) -> anyhow::Result<Vec<String>> {
+    let mut changes: Vec<String> = vec![];
+
+    let guild_roles = P2SR_SERVER.roles(http).await?;
+    let dunced_users = dunced_users(db).await?;
+
+    let mut stored_roles: HashMap<i64, Vec<RoleId>> = HashMap::new();
+    for stored_role in DunceStoredRoles::find().all(db).await? {
+        let role_id = RoleId::new(stored_role.role_id as u64);
+
+        // Roles deleted since they were stored can never be given back
+        if !guild_roles.contains_key(&role_id) {
+            if mode == ReconcileMode::Report {
+                changes.push(format!("Role {} stored for <@{}> was deleted", role_id, stored_role.user_id));
+                continue;
+            }
+
+            DunceStoredRoles::delete_many()
+                .filter(luma1_data::entity::dunce_stored_roles::Column::UserId.eq(stored_role.user_id))
+                .filter(luma1_data::entity::dunce_stored_roles::Column::RoleId.eq(stored_role.role_id))
+                .exec(db).await?;
+            changes.push(format!("Forgot deleted role {} stored for <@{}>", role_id, stored_role.user_id));
+            continue;
+        }
+
+        stored_roles.entry(stored_role.user_id).or_default().push(role_id);
+    }
+
+    // Dunced members should have the dunce role and none of their stored roles
+    for user_id in &dunced_users {
+        let Some(member) = members.get(&UserId::new(*user_id as u64)) else {
+            continue;
+        };
+
+        if !member.roles.contains(&P2SR_DUNCE_ROLE) {
+            if mode == ReconcileMode::Report {
+                changes.push(format!("{} is dunced but doesn't have the dunce role", member.mention()));
+            } else {
+                match member.add_role(http, P2SR_DUNCE_ROLE).await {
+                    Ok(()) => changes.push(format!("Gave the dunce role back to {}", member.mention())),
+                    Err(e) => changes.push(format!("Failed to give the dunce role back to {}: {}", member.mention(), e))
+                }
+            }
+        }
+
+        let held_roles: Vec<RoleId> = stored_roles.get(user_id).into_iter().flatten()
+            .filter(|role_id| member.roles.contains(role_id))
+            .copied()
+            .collect();
+        if !held_roles.is_empty() {
+            if mode == ReconcileMode::Report {
+                changes.push(format!("{} is dunced but still holds {} stored roles", member.mention(), held_roles.len()));
+                continue;
+            }
+
+            match member.remove_roles(http, &held_roles).await {
+                Ok(()) => changes.push(format!("Removed {} stored roles still held by {}", held_roles.len(), member.mention())),
+                Err(e) => changes.push(format!("Failed to remove stored roles from {}: {}", member.mention(), e))
+            }
+        }
+    }
+
+    // Members with the dunce role but no dunce time would never be undunced, so finish undunceing them
+    for member in unrecorded_dunces(members, &dunced_users) {
+        if mode != ReconcileMode::Fix {
+            changes.push(format!("{} has the dunce role but no dunce time", member.mention()));
+            continue;
+        }
+
+        match member.remove_role(http, P2SR_DUNCE_ROLE).await {
+            Ok(()) => changes.push(format!("Removed the dunce role from {} who had no dunce time", member.mention())),
+            Err(e) => changes.push(format!("Failed to remove the dunce role from {}: {}", member.mention(), e))
+        }
+    }
+
+    // Stored roles of users who are no longer dunced were left behind by an unfinished undunce
+    for (user_id, roles) in stored_roles {
+        if dunced_users.contains(&user_id) {
+            continue;
+        }
+
+        if mode == ReconcileMode::Report {
+            changes.push(format!("<@{}> is no longer dunced but still has {} stored roles", user_id, roles.len()));
+            continue;
+        }
+
+        if let Some(member) = members.get(&UserId::new(user_id as u64)) {
+            let missing_roles: Vec<RoleId> = roles.into_iter()
+                .filter(|role_id| !member.roles.contains(role_id))
+                .collect();
+            if !missing_roles.is_empty() {
+                match member.add_roles(http, &missing_roles).await {
+                    Ok(()) => changes.push(format!("Restored {} stored roles to {}", missing_roles.len(), member.mention())),
+                    Err(e) => {
+                        // Keep the stored roles around so the next run can try again
+                        changes.push(format!("Failed to restore stored roles to {}: {}", member.mention(), e));
+                        continue;
+                    }
+                }
+            }
+        }
+
+        DunceStoredRoles::delete_many()
+            .filter(luma1_data::entity::dunce_stored_roles::Column::UserId.eq(user_id))
+            .exec(db).await?;
+        changes.push(format!("Cleared leftover stored roles for <@{}>", user_id));
+    }
+
+    // History records are left open when a dunce ends outside of L3ma
+    if mode == ReconcileMode::Report {
+        let open = DunceHistory::find()
+            .filter(crate::entity::dunce_history::Column::EndedAt.is_null())
+            .filter(crate::entity::dunce_history::Column::UserId.is_not_in(dunced_users))
+            .count(db).await?;
+        if open > 0 {
+            changes.push(format!("{} dunce history records are still open for dunces that already ended", open));
+        }
+        return Ok(changes);
+    }
+
+    let closed = DunceHistory::update_many()
+        .col_expr(crate::entity::dunce_history::Column::EndedAt, sea_query::Expr::value(Utc::now()))
+        .filter(crate::entity::dunce_history::Column::EndedAt.is_null())
+        .filter(crate::entity::dunce_history::Column::UserId.is_not_in(dunced_users))
+        .exec(db).await?;
+    if closed.rows_affected > 0 {
+        changes.push(format!("Closed {} dunce history records for dunces that already ended", closed.rows_affected));
+    }
+
+    Ok(changes)
+}
+
+/// Format a list of changes to fit into a single message
+pub fn summarize_changes(changes: &[String]) -> String {
+    let mut summary = String::new();
+    for (i, change) in changes.iter().enumerate() {
+        if summary.len() + change.len() > 1800 {
+            summary.push_str(&format!("...and {} more", changes.len() - i));
+            break;
+        }
+        summary.push_str(&format!("- {}\n", change));
+    }
+
+    summary
+}
+
+/// Find and fix differences between recorded dunces and the server's roles
+#[poise::command(
+slash_command,
+rename = "reconcile",
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn dunce_reconcile(ctx: Context<'_>) -> anyhow::Result<()> {
+    ctx.defer().await?;
+
+    let members = super::dunced::fetch_members(ctx.http()).await?;
+    let unrecorded = unrecorded_dunces(&members, &dunced_users(&ctx.data().db).await?).iter()
+        .map(|member| member.mention().to_string())
+        .collect::<Vec<_>>();
+    // Taking the dunce role away can't be undone from the records, so check with the moderator first
+    let remove_unrecorded = unrecorded.is_empty() || super::confirm::confirm(ctx, "reconcile", CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .title("Remove the dunce role?")
+        .description(format!("{} members have the dunce role but no dunce time, so would never be undunced:\n{}",
+            unrecorded.len(), summarize_changes(&unrecorded)))
+    ).await?;
+
+    let mode = match remove_unrecorded {
+        true => ReconcileMode::Fix,
+        false => ReconcileMode::KeepUnrecorded
+    };
+    let changes = reconcile_dunces(ctx.http(), &ctx.data().db, &members, mode).await?;
+    if changes.is_empty() {
+        ctx.say("Dunces are already consistent with the server, nothing was changed").await?;
+        return Ok(());
+    }
+
+    let summary = summarize_changes(&changes);
+    super::send_mod_action_log(ctx.http(), ctx.author().clone(), |embed| {
+        embed.description(format!("Reconciled dunces\n{}", summary))
+    }).await?;
+
+    ctx.say(format!("Made {} changes:\n{}", changes.len(), summary)).await?;
+
+    Ok(())
+}
+
+/// Check dunces on startup, reporting anything that has drifted to the mod log.
+/// Nothing is changed, fixing what was found is left to a confirmed `/dunce reconcile`.
+pub async fn reconcile_on_startup(http: &Http, db: &DatabaseConnection, bot_user: poise::serenity_prelude::User) -> anyhow::Result<()> {
+    let members = super::dunced::fetch_members(http).await?;
+    let findings = reconcile_dunces(http, db, &members, ReconcileMode::Report).await?;
+    if findings.is_empty() {
+        return Ok(());
+    }
+
+    let summary = summarize_changes(&findings);
+    super::send_mod_action_log(http, bot_user, |embed| {
+        embed.description(format!("Dunces don't line up with the server, run /dunce reconcile to fix them\n{}", summary))
+    }).await?;
+
+    Ok(())
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+
+use anyhow::anyhow;
+use chrono::Utc;
+use poise::CreateReply;
+use poise::serenity_prelude::{ChannelId, CreateMessage, EditMessage, Message, MessageId, User, UserId};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use luma1_data::sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, Set, sea_query};
+
+use crate::context::{Context, Data};
+use crate::entity::{report_reporters, reports};
+use crate::entity::reports::ReportStatus;
+use crate::entity::prelude::*;
+use crate::events::reports::{alert_components, alert_embed, delete_message, dismiss, dunce_reported_user, load_report, open_action, warn_reported_user,
+    DELETE_PREFIX, DISMISS_PREFIX, DUNCE_PREFIX, DUNCE_SUBMIT_PREFIX, WARN_PREFIX, WARN_SUBMIT_PREFIX};
+use crate::events::router::{Route, RouteHandler};
+
+/// What a report is about, reports of the same thing are added to one open report
+type ReportKey = (UserId, Option<MessageId>);
+
+static FILE_REPORT_LOCKS: LazyLock<std::sync::Mutex<HashMap<ReportKey, Arc<Mutex<()>>>>> =
+    LazyLock::new(Default::default);
+
+/// Held while a report is filed, so reports of the same user or message are filed one at a time
+/// while reports of anything else go ahead
+struct FileReportLock {
+    key: ReportKey,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl FileReportLock {
+    async fn acquire(key: ReportKey) -> Self {
+        let lock = FILE_REPORT_LOCKS.lock().unwrap().entry(key).or_default().clone();
+        FileReportLock { key, guard: Some(lock.lock_owned().await) }
+    }
+}
+
+impl Drop for FileReportLock {
+    fn drop(&mut self) {
+        self.guard.take();
+
+        // Forget the lock once nobody else is waiting on it
+        let mut locks = FILE_REPORT_LOCKS.lock().unwrap();
+        if locks.get(&self.key).is_some_and(|lock| Arc::strong_count(lock) == 1) {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+#[derive(poise::Modal)]
+#[name = "Report message"]
+struct ReportModal {
+    #[name = "Why are you reporting this message?"]
+    #[paragraph]
+    #[max_length = 500]
+    reason: Option<String>,
+}
+
+/// Send a report to the staff, adding to an open report of the same user or message instead of raising another alert
+async fn file_report(ctx: Context<'_>, reported: &User, message: Option<&Message>, reason: Option<String>) -> anyhow::Result<()> {
+    let report_channel = ctx.data().report_channel
+        .ok_or(anyhow!("Reports aren't set up on this server"))?;
+    let db = &ctx.data().db;
+
+    // Reports of the same thing are filed one at a time, so a burst of them shares one report and
+    // one alert instead of each finding nothing open and raising their own
+    let lock = FileReportLock::acquire((reported.id, message.map(|message| message.id))).await;
+
+    let mut existing = Reports::find()
+        .filter(reports::Column::Status.eq(ReportStatus::Open))
+        .filter(reports::Column::ReportedUserId.eq(reported.id.get() as i64));
+    existing = match message {
+        Some(message) => existing.filter(reports::Column::MessageId.eq(message.id.get() as i64)),
+        None => existing.filter(reports::Column::MessageId.is_null())
+    };
+
+    let report = match existing.one(db).await? {
+        Some(report) => report,
+        None => reports::ActiveModel {
+            reported_user_id: Set(reported.id.get() as i64),
+            channel_id: Set(message.map(|message| message.channel_id.get() as i64)),
+            message_id: Set(message.map(|message| message.id.get() as i64)),
+            content: Set(message.map(|message| message.content.clone())),
+            attachments: Set(message
+                .filter(|message| !message.attachments.is_empty())
+                .map(|message| message.attachments.iter()
+                    .map(|attachment| attachment.url.clone())
+                    .collect::<Vec<_>>()
+                    .join("\n"))),
+            message_deleted: Set(false),
+            status: Set(ReportStatus::Open),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }.insert(db).await?
+    };
+
+    // The same person reporting twice only counts once
+    ReportReporters::insert(report_reporters::ActiveModel {
+        report_id: Set(report.id),
+        reporter_id: Set(ctx.author().id.get() as i64),
+        reason: Set(reason),
+        reported_at: Set(Utc::now())
+    }).on_conflict(
+        sea_query::OnConflict::columns([report_reporters::Column::ReportId, report_reporters::Column::ReporterId])
+            .do_nothing().to_owned()
+    ).do_nothing().exec(db).await?;
+
+    let (report, reporters) = load_report(ctx.data(), report.id).await?
+        .ok_or(anyhow!("Report disappeared while it was being filed"))?;
+    match report.alert_message_id {
+        Some(alert_message_id) => {
+            report_channel.edit_message(ctx, MessageId::new(alert_message_id as u64), EditMessage::new()
+                .embed(alert_embed(&report, &reporters))
+                .components(alert_components(&report))
+            ).await?;
+        }
+        None => post_alert(ctx, report_channel, report, &reporters).await?
+    }
+    drop(lock);
+
+    ctx.send(CreateReply::default()
+        .ephemeral(true)
+        .content("Thanks, your report was sent to the moderators")
+    ).await?;
+
+    Ok(())
+}
+
+async fn post_alert(
+    ctx: Context<'_>,
+    report_channel: ChannelId,
+    report: reports::Model,
+    reporters: &[report_reporters::Model]
+) -> anyhow::Result<()> {
+    let alert = report_channel.send_message(ctx, CreateMessage::new()
+        .embed(alert_embed(&report, reporters))
+        .components(alert_components(&report))
+    ).await?;
+
+    let mut record: reports::ActiveModel = report.into();
+    record.alert_message_id = Set(Some(alert.id.get() as i64));
+    record.update(&ctx.data().db).await?;
+
+    Ok(())
+}
+
+/// Report a user to the moderators
+#[poise::command(
+slash_command,
+guild_only,
+ephemeral = true,
+on_error = "crate::commands::error_handler"
+)]
+pub async fn report(
+    ctx: Context<'_>,
+    #[description = "User to report"] user: User,
+    #[description = "What they did"] #[max_length = 500] reason: String
+) -> anyhow::Result<()> {
+    if user.id == ctx.author().id {
+        anyhow::bail!("You can't report yourself");
+    }
+
+    file_report(ctx, &user, None, Some(reason)).await
+}
+
+/// Report a message to the moderators
+#[poise::command(
+context_menu_command = "Report message",
+guild_only,
+ephemeral = true,
+on_error = "crate::commands::error_handler"
+)]
+pub async fn report_message(
+    ctx: poise::ApplicationContext<'_, Data, anyhow::Error>,
+    message: Message
+) -> anyhow::Result<()> {
+    if message.author.id == ctx.author().id {
+        anyhow::bail!("You can't report your own message");
+    }
+
+    // Closing the form without submitting it cancels the report
+    let Some(form) = poise::execute_modal(ctx, None::<ReportModal>, None).await? else {
+        return Ok(());
+    };
+
+    file_report(ctx.into(), &message.author, Some(&message), form.reason).await
+}
+
+/// The action buttons on report alerts and the forms they open
+pub fn routes() -> Vec<Route> {
+    vec![
+        Route { name: DELETE_PREFIX, handler: RouteHandler::Component(|ctx, data, component, args| Box::pin(async move {
+            delete_message(ctx, data, component, args.get(0)?).await
+        })) },
+        Route { name: DUNCE_PREFIX, handler: RouteHandler::Component(|ctx, data, component, args| Box::pin(async move {
+            open_action(ctx, data, component, args.get(0)?, true).await
+        })) },
+        Route { name: WARN_PREFIX, handler: RouteHandler::Component(|ctx, data, component, args| Box::pin(async move {
+            open_action(ctx, data, component, args.get(0)?, false).await
+        })) },
+        Route { name: DISMISS_PREFIX, handler: RouteHandler::Component(|ctx, data, component, args| Box::pin(async move {
+            dismiss(ctx, data, component, args.get(0)?).await
+        })) },
+        Route { name: DUNCE_SUBMIT_PREFIX, handler: RouteHandler::Modal(|ctx, data, modal, args| Box::pin(async move {
+            dunce_reported_user(ctx, data, modal, args.get(0)?).await
+        })) },
+        Route { name: WARN_SUBMIT_PREFIX, handler: RouteHandler::Modal(|ctx, data, modal, args| Box::pin(async move {
+            warn_reported_user(ctx, data, modal, args.get(0)?).await
+        })) },
+    ]
+}
@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+
+use poise::serenity_prelude::{Http, Member, Role, RoleId, UserId};
+
+use crate::context::{P2SR_DUNCE_ROLE, P2SR_SERVER};
+
+/// Position of the highest of the given roles, 0 being @everyone
+pub fn highest_position(guild_roles: &HashMap<RoleId, Role>, roles: &[RoleId]) -> u16 {
+    roles.iter()
+        .filter_map(|role_id| guild_roles.get(role_id))
+        .map(|role| role.position)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Whether Discord would let the bot take a role away from someone
+fn is_removable(role: &Role, bot_position: u16) -> bool {
+    // Booster and integration roles are managed by Discord, and roles at or
+    // above the bot's highest role are out of its reach
+    !role.managed && !role.tags.premium_subscriber && role.position < bot_position
+}
+
+/// Split a member's roles (other than the dunce role) into those the bot can remove and those it has to leave alone
+pub async fn split_removable_roles(http: &Http, bot_id: UserId, member: &Member) -> anyhow::Result<(Vec<RoleId>, Vec<RoleId>)> {
+    let guild_roles = P2SR_SERVER.roles(http).await?;
+    let bot_member = P2SR_SERVER.member(http, bot_id).await?;
+    let bot_position = highest_position(&guild_roles, &bot_member.roles);
+
+    Ok(member.roles.iter()
+        .filter(|role_id| **role_id != P2SR_DUNCE_ROLE)
+        .partition(|role_id| guild_roles.get(role_id).is_some_and(|role| is_removable(role, bot_position))))
+}
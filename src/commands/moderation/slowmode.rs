@@ -0,0 +1,129 @@
+use anyhow::anyhow;
+use chrono::Utc;
+use poise::serenity_prelude::{ChannelId, EditChannel, HttpError, Mentionable};
+use serenity::prelude::Context as SerenityContext;
+
+use luma1_data::sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, Set, sea_query};
+
+use crate::context::Context;
+use crate::entity::prelude::*;
+use crate::commands::Duration;
+
+/// Discord error codes for a channel that was deleted or that the bot can no longer see
+const UNKNOWN_CHANNEL: isize = 10003;
+const MISSING_ACCESS: isize = 50001;
+
+fn describe_rate_limit(seconds: u16) -> String {
+    if seconds == 0 {
+        "off".to_string()
+    } else {
+        format!("{} seconds", seconds)
+    }
+}
+
+/// Set the slowmode of this channel, optionally reverting it later
+#[poise::command(
+slash_command,
+guild_only,
+required_permissions = "MANAGE_CHANNELS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn slowmode(
+    ctx: Context<'_>,
+    #[description = "Seconds between messages (0 to disable)"] #[max = 21600] seconds: u16,
+    #[description = "Revert to the current slowmode after this long, like 30m or 2h"] duration: Option<Duration>
+) -> anyhow::Result<()> {
+    let channel = ctx.guild_channel().await
+        .ok_or(anyhow!("Slowmode can only be set in server channels"))?;
+    let channel_mention = channel.mention();
+    let channel_id = channel.id;
+
+    // Calculate when to revert, if at all
+    let revert_time = duration.map(|duration| duration.after(Utc::now())).transpose()?;
+
+    // If a revert is already pending, keep reverting to the value from before it was scheduled
+    let pending_revert = SlowmodeReverts::find_by_id(channel_id.get() as i64)
+        .one(&ctx.data().db).await?;
+    let previous_rate_limit = match &pending_revert {
+        Some(pending) => pending.previous_rate_limit as u16,
+        None => channel.rate_limit_per_user.unwrap_or(0)
+    };
+
+    channel_id.edit(ctx.http(), EditChannel::new().rate_limit_per_user(seconds)).await
+        .map_err(|e| anyhow::Error::new(e).context("Could not set slowmode"))?;
+
+    match revert_time {
+        Some(revert_time) => {
+            SlowmodeReverts::insert(crate::entity::slowmode_reverts::ActiveModel {
+                channel_id: Set(channel_id.into()),
+                previous_rate_limit: Set(previous_rate_limit as i32),
+                revert_instant: Set(revert_time)
+            }).on_conflict(
+                sea_query::OnConflict::column(crate::entity::slowmode_reverts::Column::ChannelId)
+                    .update_column(crate::entity::slowmode_reverts::Column::RevertInstant)
+                    .to_owned()
+            ).exec(&ctx.data().db).await?;
+        }
+        None => {
+            // A permanent change replaces any scheduled revert
+            if let Some(pending_revert) = pending_revert {
+                SlowmodeReverts::delete(pending_revert.into_active_model())
+                    .exec(&ctx.data().db).await?;
+            }
+        }
+    }
+
+    super::send_mod_action_log(ctx.http(), ctx.author().clone(), |embed| {
+        let embed = embed.description(format!("Set slowmode in {} to {}", channel_mention, describe_rate_limit(seconds)));
+        match revert_time {
+            Some(revert_time) => embed
+                .field("Reverts to", describe_rate_limit(previous_rate_limit), true)
+                .field("Reverts", format!("<t:{}:R>", revert_time.timestamp()), true),
+            None => embed
+        }
+    }).await?;
+
+    match revert_time {
+        Some(revert_time) => ctx.say(format!("Set slowmode to {}, it will revert to {} <t:{}:R>",
+            describe_rate_limit(seconds), describe_rate_limit(previous_rate_limit), revert_time.timestamp())).await?,
+        None => ctx.say(format!("Set slowmode to {}", describe_rate_limit(seconds))).await?
+    };
+
+    Ok(())
+}
+
+/// Put back the slowmode of every channel whose scheduled revert has passed
+pub async fn revert_expired(ctx: &SerenityContext, db: &DatabaseConnection) -> anyhow::Result<()> {
+    let expired = SlowmodeReverts::find()
+        .filter(crate::entity::slowmode_reverts::Column::RevertInstant.lte(Utc::now()))
+        .all(db).await?;
+
+    for revert in expired {
+        let channel_id = ChannelId::new(revert.channel_id as u64);
+        let previous_rate_limit = revert.previous_rate_limit as u16;
+
+        match channel_id.edit(&ctx.http, EditChannel::new().rate_limit_per_user(previous_rate_limit)).await {
+            Ok(_) => {}
+            // The revert can never succeed, so drop it instead of retrying forever
+            Err(serenity::Error::Http(HttpError::UnsuccessfulRequest(response)))
+                if [UNKNOWN_CHANNEL, MISSING_ACCESS].contains(&response.error.code) => {
+                eprintln!("Dropping slowmode revert for channel {}: {}", channel_id, response.error.message);
+                SlowmodeReverts::delete(revert.into_active_model()).exec(db).await?;
+                continue;
+            }
+            // Anything else is retried on the next run
+            Err(e) => {
+                eprintln!("Failed to revert slowmode in channel {}: {:?}", channel_id, e);
+                continue;
+            }
+        }
+        SlowmodeReverts::delete(revert.into_active_model()).exec(db).await?;
+
+        let bot_user = ctx.cache.current_user().clone().into();
+        super::send_mod_action_log(&ctx.http, bot_user, |embed| {
+            embed.description(format!("Reverted slowmode in {} to {}", channel_id.mention(), describe_rate_limit(previous_rate_limit)))
+        }).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use poise::serenity_prelude::{ChannelId, CreateAttachment, Message};
+use chrono::Utc;
+
+/// Render messages as a plain text transcript, oldest first
+pub fn build_transcript(channel_id: ChannelId, messages: &[Message]) -> String {
+    let mut sorted: Vec<&Message> = messages.iter().collect();
+    sorted.sort_by_key(|message| message.id);
+
+    let mut transcript = format!(
+        "Transcript of {} messages from channel {}\nExported {}\n",
+        sorted.len(),
+        channel_id,
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
+    );
+
+    for message in sorted {
+        transcript.push_str(&format!(
+            "\n[{}] {} ({}): {}\n",
+            message.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+            message.author.name,
+            message.author.id,
+            message.content
+        ));
+        for attachment in &message.attachments {
+            transcript.push_str(&format!("    Attachment: {}\n", attachment.url));
+        }
+    }
+
+    transcript
+}
+
+/// Wrap a transcript into a file ready to upload
+pub fn transcript_attachment(transcript: String) -> CreateAttachment {
+    CreateAttachment::bytes(transcript, format!("transcript-{}.txt", Utc::now().timestamp()))
+}
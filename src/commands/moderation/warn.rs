@@ -0,0 +1,91 @@
+use chrono::Utc;
+use poise::serenity_prelude::{Color, CreateEmbed, CreateEmbedFooter, CreateMessage, Http, Mentionable, Timestamp, User};
+
+use luma1_data::sea_orm::{ActiveModelTrait, DatabaseConnection, Set};
+
+use crate::context::Context;
+use crate::entity::warnings;
+
+fn dm_embed(reason: &str) -> CreateEmbed {
+    CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .title("You have been warned by a moderator")
+        .field("Reason", reason, false)
+        .footer(CreateEmbedFooter::new("Portal 2 Speedrun Server"))
+        .timestamp(Timestamp::now())
+}
+
+/// Record a warning, log it and DM it to the user, returning a summary for the moderator
+pub async fn warn_user(http: &Http, db: &DatabaseConnection, moderator: &User, user: &User, reason: String) -> anyhow::Result<String> {
+    warnings::ActiveModel {
+        user_id: Set(user.id.get() as i64),
+        moderator_id: Set(moderator.id.get() as i64),
+        reason: Set(reason.clone()),
+        warned_at: Set(Utc::now()),
+        ..Default::default()
+    }.insert(db).await?;
+
+    let log_result = super::send_mod_action_log(http, moderator.clone(), |embed| {
+        embed.description(format!("Warned {} ({})", user.mention(), user.id))
+            .field("Reason", &reason, false)
+    }).await;
+
+    let dm_result = async {
+        let channel = user.create_dm_channel(http).await?;
+        channel.send_message(http, CreateMessage::new().embed(dm_embed(&reason))).await?;
+        Ok::<_, anyhow::Error>(())
+    }.await;
+
+    let mut response = format!("Warned user {} ({})", user.mention(), user.id);
+    if let Err(e) = log_result {
+        response.push_str(&format!("\n```diff\n- {:#}\n```", e));
+    }
+    if dm_result.is_err() {
+        response.push_str("\n```diff\n- Unable to DM User\n```");
+    }
+    if let Some(warning) = super::alts::linked_accounts_warning(db, user.id).await {
+        response.push_str(&format!("\n{}", warning));
+    }
+
+    Ok(response)
+}
+
+/// Warn a user and DMs them the reason
+#[poise::command(
+slash_command,
+required_permissions = "MODERATE_MEMBERS",
+on_error = "crate::commands::error_handler"
+)]
+pub async fn warn(
+    ctx: Context<'_>,
+    #[description = "User to warn"] user: User,
+    #[description = "Reason to record/DM"] reason: String,
+    #[description = "Show what would happen without warning"] dry_run: Option<bool>
+) -> anyhow::Result<()> {
+    apply_warn(ctx, user, reason, dry_run.unwrap_or(false)).await
+}
+
+/// Warn a user after the usual checks and confirmation
+pub async fn apply_warn(ctx: Context<'_>, user: User, reason: String, dry_run: bool) -> anyhow::Result<()> {
+    super::checks::ensure_can_moderate(ctx, &user).await?;
+
+    let mut summary = CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .title("Warn user?")
+        .description(format!("{} ({})", user.mention(), user.id))
+        .field("Reason", &reason, false);
+    if let Some(warning) = super::alts::linked_accounts_warning(&ctx.data().db, user.id).await {
+        summary = summary.field("Alts", warning, false);
+    }
+    if dry_run {
+        return super::confirm::show_dry_run(ctx, summary, Some(dm_embed(&reason)), vec![]).await;
+    }
+    if !super::confirm::confirm(ctx, "warn", summary).await? {
+        return Ok(());
+    }
+
+    let response = warn_user(ctx.http(), &ctx.data().db, ctx.author(), &user, reason).await?;
+    ctx.say(response).await?;
+
+    Ok(())
+}
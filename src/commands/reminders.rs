@@ -0,0 +1,177 @@
+use anyhow::{anyhow, bail};
+use chrono::Utc;
+use poise::CreateReply;
+use poise::serenity_prelude::{ChannelId, CreateAllowedMentions, CreateMessage, Mentionable, UserId};
+use serenity::prelude::Context as SerenityContext;
+
+use luma1_data::sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, Set};
+
+use crate::context::Context;
+use crate::entity::reminders;
+use crate::entity::prelude::*;
+use super::When;
+
+/// Most reminders a user can have pending at once
+const MAX_PENDING_REMINDERS: u64 = 25;
+/// Longest a reminder's message is shown in /reminders list, so a page of them fits in one message
+const PREVIEW_LENGTH: usize = 100;
+
+fn preview(message: &str) -> String {
+    match message.chars().count() > PREVIEW_LENGTH {
+        true => format!("{}...", message.chars().take(PREVIEW_LENGTH).collect::<String>()),
+        false => message.to_string()
+    }
+}
+
+/// Set a reminder
+#[poise::command(
+slash_command,
+on_error = "crate::commands::error_handler"
+)]
+pub async fn remindme(
+    ctx: Context<'_>,
+    #[description = "When to remind you, like 2h30m or a UTC date (YYYY-MM-DD HH:MM)"] #[rename = "in"] time: When,
+    #[description = "What to remind you about"] #[max_length = 1000] message: String,
+    #[description = "Send the reminder by DM instead of in this channel"] dm: Option<bool>
+) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let remind_at = time.resolve(now)?;
+    if remind_at <= now {
+        bail!("Reminders have to be in the future");
+    }
+
+    let pending = Reminders::find()
+        .filter(reminders::Column::UserId.eq(ctx.author().id.get() as i64))
+        .count(&ctx.data().db).await?;
+    if pending >= MAX_PENDING_REMINDERS {
+        bail!("You already have {} reminders pending, cancel some with /reminders cancel", pending);
+    }
+
+    // Reminders set in DMs can only be delivered by DM
+    let in_channel = ctx.guild_id().is_some() && !dm.unwrap_or(false);
+    let reminder = reminders::ActiveModel {
+        user_id: Set(ctx.author().id.get() as i64),
+        channel_id: Set(in_channel.then(|| ctx.channel_id().get() as i64)),
+        message: Set(message),
+        created_at: Set(now),
+        remind_at: Set(remind_at),
+        ..Default::default()
+    }.insert(&ctx.data().db).await?;
+
+    ctx.send(CreateReply::default()
+        .ephemeral(!in_channel)
+        .content(format!("Reminder `#{}` set for <t:{}:f> (<t:{}:R>){}",
+            reminder.id, remind_at.timestamp(), remind_at.timestamp(),
+            if in_channel { "" } else { ", it will be sent by DM" }))
+    ).await?;
+
+    Ok(())
+}
+
+/// Manage your reminders
+#[poise::command(
+slash_command,
+subcommands("reminders_list", "reminders_cancel"),
+subcommand_required
+)]
+pub async fn reminders(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// List your pending reminders
+#[poise::command(
+slash_command,
+rename = "list",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn reminders_list(ctx: Context<'_>) -> anyhow::Result<()> {
+    let reminders = Reminders::find()
+        .filter(reminders::Column::UserId.eq(ctx.author().id.get() as i64))
+        .order_by_asc(reminders::Column::RemindAt)
+        .all(&ctx.data().db).await?;
+    if reminders.is_empty() {
+        ctx.say("You have no pending reminders").await?;
+        return Ok(());
+    }
+
+    let entries: Vec<String> = reminders.iter().map(|reminder| {
+        format!("`#{}` <t:{}:R> {} - {}",
+            reminder.id,
+            reminder.remind_at.timestamp(),
+            reminder.channel_id.map(|channel_id| format!("in <#{}>", channel_id)).unwrap_or("by DM".to_string()),
+            preview(&reminder.message))
+    }).collect();
+    let pages: Vec<String> = entries.chunks(10)
+        .map(|chunk| format!("**Your reminders ({})**\n{}", entries.len(), chunk.join("\n")))
+        .collect();
+    let pages: Vec<&str> = pages.iter().map(|page| page.as_str()).collect();
+    poise::builtins::paginate(ctx, &pages).await?;
+
+    Ok(())
+}
+
+/// Cancel one of your reminders
+#[poise::command(
+slash_command,
+rename = "cancel",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn reminders_cancel(
+    ctx: Context<'_>,
+    #[description = "Number of the reminder, shown by /reminders list"] id: i32
+) -> anyhow::Result<()> {
+    let reminder = Reminders::find_by_id(id).one(&ctx.data().db).await?
+        .filter(|reminder| reminder.user_id == ctx.author().id.get() as i64);
+    let Some(reminder) = reminder else {
+        ctx.say(format!("```diff\n- You have no reminder #{}.\n```", id)).await?;
+        return Ok(());
+    };
+    reminder.delete(&ctx.data().db).await?;
+
+    ctx.say(format!("Cancelled reminder `#{}`", id)).await?;
+
+    Ok(())
+}
+
+/// Send every reminder that has come due
+pub async fn deliver_due(ctx: &SerenityContext, db: &DatabaseConnection) -> anyhow::Result<()> {
+    let due = Reminders::find()
+        .filter(reminders::Column::RemindAt.lte(Utc::now()))
+        .all(db).await?;
+
+    for reminder in due {
+        let user_id = UserId::new(reminder.user_id as u64);
+        let content = format!("⏰ {}, <t:{}:R> you asked to be reminded:\n{}",
+            user_id.mention(), reminder.created_at.timestamp(), reminder.message);
+        let message = CreateMessage::new()
+            .content(&content)
+            .allowed_mentions(CreateAllowedMentions::new().users(vec![user_id]));
+
+        // Fall back to a DM if the channel is gone or the bot can't post there anymore
+        let mut result = Err(anyhow!("Reminder has no channel"));
+        if let Some(channel_id) = reminder.channel_id {
+            result = ChannelId::new(channel_id as u64).send_message(&ctx.http, message.clone()).await
+                .map(|_| ()).map_err(anyhow::Error::from);
+        }
+        if result.is_err() {
+            result = async {
+                let channel = user_id.create_dm_channel(&ctx.http).await?;
+                channel.send_message(&ctx.http, message).await?;
+                Ok(())
+            }.await;
+        }
+        if let Err(e) = result {
+            eprintln!("Failed to deliver reminder {} to {}: {:?}", reminder.id, user_id, e);
+        }
+
+        // Undeliverable reminders are dropped rather than retried forever
+        let reminder_id = reminder.id;
+        if let Err(e) = reminder.delete(db).await {
+            eprintln!("Failed to remove delivered reminder {}: {:?}", reminder_id, e);
+        }
+    }
+
+    Ok(())
+}
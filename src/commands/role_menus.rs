@@ -0,0 +1,299 @@
+use anyhow::{anyhow, bail};
+use chrono::Utc;
+use poise::serenity_prelude::{ChannelId, CreateMessage, EditMessage, GuildChannel, Mentionable, MessageId, Permissions, Role};
+
+use luma1_data::sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, ModelTrait, QueryFilter, QueryOrder, Set};
+
+use crate::context::{Context, P2SR_SERVER};
+use crate::entity::{role_menu_entries, role_menus};
+use crate::entity::role_menus::RoleMenuStyle;
+use crate::entity::prelude::*;
+use crate::events::role_menus::{load_menu, menu_components, menu_embed, parse_emoji, same_emoji, select_roles, toggle_role, BUTTON_PREFIX, MAX_ENTRIES, SELECT_PREFIX};
+use crate::events::router::{Route, RouteHandler};
+use super::moderation::highest_position;
+
+/// Roles with any of these permissions are never handed out by a menu
+const STAFF_PERMISSIONS: Permissions = Permissions::ADMINISTRATOR
+    .union(Permissions::MANAGE_GUILD)
+    .union(Permissions::MANAGE_ROLES)
+    .union(Permissions::MANAGE_CHANNELS)
+    .union(Permissions::MANAGE_MESSAGES)
+    .union(Permissions::KICK_MEMBERS)
+    .union(Permissions::BAN_MEMBERS)
+    .union(Permissions::MODERATE_MEMBERS);
+
+async fn find_menu(ctx: Context<'_>, menu_id: i32) -> anyhow::Result<(role_menus::Model, Vec<role_menu_entries::Model>)> {
+    load_menu(&ctx.data().db, menu_id).await?
+        .ok_or(anyhow!("There is no role menu #{}, see /rolemenu list", menu_id))
+}
+
+/// Redraw a menu's message after its roles changed
+async fn refresh_menu(ctx: Context<'_>, menu: &role_menus::Model, entries: &[role_menu_entries::Model]) -> anyhow::Result<()> {
+    ChannelId::new(menu.channel_id as u64).edit_message(ctx, MessageId::new(menu.message_id as u64), EditMessage::new()
+        .embed(menu_embed(menu, entries))
+        .components(menu_components(menu, entries))
+    ).await?;
+
+    Ok(())
+}
+
+/// Let members give themselves roles
+#[poise::command(
+slash_command,
+guild_only,
+required_permissions = "MANAGE_ROLES",
+subcommands("rolemenu_create", "rolemenu_add", "rolemenu_remove", "rolemenu_delete", "rolemenu_list"),
+subcommand_required
+)]
+pub async fn rolemenu(_ctx: Context<'_>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Post a new role menu, then add roles to it with /rolemenu add
+#[poise::command(
+slash_command,
+rename = "create",
+guild_only,
+required_permissions = "MANAGE_ROLES",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn rolemenu_create(
+    ctx: Context<'_>,
+    #[description = "How members pick roles"] style: RoleMenuStyle,
+    #[description = "Title of the menu"] #[max_length = 256] title: String,
+    #[description = "Text shown above the roles"] #[max_length = 2000] description: Option<String>,
+    #[description = "Most roles from this menu a member can have"] #[min = 1] #[max = 25] max_roles: Option<u8>,
+    #[description = "Role members need to use this menu"] required_role: Option<Role>,
+    #[description = "Channel to post in (default: this one)"] channel: Option<GuildChannel>
+) -> anyhow::Result<()> {
+    let channel_id = channel.map(|channel| channel.id).unwrap_or(ctx.channel_id());
+
+    let mut menu = role_menus::Model {
+        id: 0,
+        channel_id: channel_id.get() as i64,
+        message_id: 0,
+        style,
+        title,
+        description,
+        max_roles: max_roles.map(|max_roles| max_roles as i32),
+        required_role_id: required_role.map(|role| role.id.get() as i64),
+        created_by: ctx.author().id.get() as i64,
+        created_at: Utc::now(),
+    };
+    let message = channel_id.send_message(ctx, CreateMessage::new().embed(menu_embed(&menu, &[]))).await
+        .map_err(|e| anyhow::Error::new(e).context(format!("Could not post in {}", channel_id.mention())))?;
+    menu.message_id = message.id.get() as i64;
+
+    let menu = role_menus::ActiveModel {
+        channel_id: Set(menu.channel_id),
+        message_id: Set(menu.message_id),
+        style: Set(menu.style),
+        title: Set(menu.title),
+        description: Set(menu.description),
+        max_roles: Set(menu.max_roles),
+        required_role_id: Set(menu.required_role_id),
+        created_by: Set(menu.created_by),
+        created_at: Set(menu.created_at),
+        ..Default::default()
+    }.insert(&ctx.data().db).await?;
+
+    ctx.say(format!("Posted role menu `#{}` in {}, add roles to it with /rolemenu add", menu.id, channel_id.mention())).await?;
+
+    Ok(())
+}
+
+/// Offer a role in a role menu
+#[poise::command(
+slash_command,
+rename = "add",
+guild_only,
+required_permissions = "MANAGE_ROLES",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn rolemenu_add(
+    ctx: Context<'_>,
+    #[description = "Number of the role menu, shown by /rolemenu list"] menu: i32,
+    #[description = "Role to offer"] role: Role,
+    #[description = "Name shown on the button or option (default: the role name)"] #[max_length = 80] label: Option<String>,
+    #[description = "Emoji to show, required for reaction menus"] emoji: Option<String>,
+    #[description = "Short explanation of the role"] #[max_length = 100] description: Option<String>
+) -> anyhow::Result<()> {
+    let (menu, entries) = find_menu(ctx, menu).await?;
+
+    if role.id.get() == P2SR_SERVER.get() || role.managed {
+        bail!("{} can't be given out by a role menu", role.name);
+    }
+    if role.permissions.intersects(STAFF_PERMISSIONS) {
+        bail!("{} has moderation permissions, so it can't be self-assigned", role.name);
+    }
+    let guild_roles = P2SR_SERVER.roles(ctx).await?;
+    let bot_member = P2SR_SERVER.member(ctx, ctx.framework().bot_id).await?;
+    if role.position >= highest_position(&guild_roles, &bot_member.roles) {
+        bail!("{} is above my highest role, so I can't give it out", role.name);
+    }
+
+    if entries.len() >= MAX_ENTRIES {
+        bail!("Role menus can offer at most {} roles", MAX_ENTRIES);
+    }
+    if entries.iter().any(|entry| entry.role_id == role.id.get() as i64) {
+        bail!("{} is already in this menu", role.name);
+    }
+
+    let reaction = match &emoji {
+        Some(emoji) => Some(parse_emoji(emoji).ok_or(anyhow!("\"{}\" isn't an emoji", emoji))?),
+        None => None
+    };
+    if menu.style == RoleMenuStyle::Reactions && reaction.is_none() {
+        bail!("Reaction menus need an emoji for every role");
+    }
+    if menu.style == RoleMenuStyle::Reactions && let Some(reaction) = &reaction
+        && entries.iter().filter_map(|entry| entry.emoji.as_deref().and_then(parse_emoji)).any(|other| same_emoji(&other, reaction)) {
+        bail!("Another role in this menu already uses that emoji");
+    }
+
+    let entry = role_menu_entries::ActiveModel {
+        menu_id: Set(menu.id),
+        role_id: Set(role.id.get() as i64),
+        label: Set(label.unwrap_or(role.name.clone())),
+        emoji: Set(emoji.map(|emoji| emoji.trim().to_string())),
+        description: Set(description),
+        position: Set(entries.last().map(|entry| entry.position + 1).unwrap_or(0)),
+    }.insert(&ctx.data().db).await?;
+
+    let mut entries = entries;
+    entries.push(entry.clone());
+    let result = async {
+        if let (RoleMenuStyle::Reactions, Some(reaction)) = (menu.style, reaction) {
+            ChannelId::new(menu.channel_id as u64).create_reaction(ctx, MessageId::new(menu.message_id as u64), reaction).await?;
+        }
+        refresh_menu(ctx, &menu, &entries).await
+    }.await;
+    // A bad emoji only shows up once Discord rejects it
+    if let Err(e) = result {
+        entry.delete(&ctx.data().db).await?;
+        return Err(e.context("Could not update the role menu, is the emoji from this server?"));
+    }
+
+    ctx.say(format!("Added {} to role menu `#{}`", role.mention(), menu.id)).await?;
+
+    Ok(())
+}
+
+/// Stop offering a role in a role menu
+#[poise::command(
+slash_command,
+rename = "remove",
+guild_only,
+required_permissions = "MANAGE_ROLES",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn rolemenu_remove(
+    ctx: Context<'_>,
+    #[description = "Number of the role menu, shown by /rolemenu list"] menu: i32,
+    #[description = "Role to stop offering"] role: Role
+) -> anyhow::Result<()> {
+    let (menu, mut entries) = find_menu(ctx, menu).await?;
+    let Some(index) = entries.iter().position(|entry| entry.role_id == role.id.get() as i64) else {
+        bail!("{} isn't in this menu", role.name);
+    };
+    let entry = entries.remove(index);
+    let emoji = entry.emoji.clone();
+    entry.delete(&ctx.data().db).await?;
+
+    refresh_menu(ctx, &menu, &entries).await?;
+    if let (RoleMenuStyle::Reactions, Some(reaction)) = (menu.style, emoji.as_deref().and_then(parse_emoji)) {
+        ChannelId::new(menu.channel_id as u64).delete_reaction_emoji(ctx, MessageId::new(menu.message_id as u64), reaction).await?;
+    }
+
+    // Members keep the role, only the menu stops offering it
+    ctx.say(format!("Removed {} from role menu `#{}`", role.mention(), menu.id)).await?;
+
+    Ok(())
+}
+
+/// Delete a role menu and its message
+#[poise::command(
+slash_command,
+rename = "delete",
+guild_only,
+required_permissions = "MANAGE_ROLES",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn rolemenu_delete(
+    ctx: Context<'_>,
+    #[description = "Number of the role menu, shown by /rolemenu list"] menu: i32
+) -> anyhow::Result<()> {
+    let (menu, _) = find_menu(ctx, menu).await?;
+
+    // The message may already have been deleted by hand
+    let message_deleted = ChannelId::new(menu.channel_id as u64)
+        .delete_message(ctx, MessageId::new(menu.message_id as u64)).await.is_ok();
+
+    RoleMenuEntries::delete_many()
+        .filter(role_menu_entries::Column::MenuId.eq(menu.id))
+        .exec(&ctx.data().db).await?;
+    let menu_id = menu.id;
+    menu.delete(&ctx.data().db).await?;
+
+    ctx.say(match message_deleted {
+        true => format!("Deleted role menu `#{}`", menu_id),
+        false => format!("Deleted role menu `#{}`, its message was already gone", menu_id)
+    }).await?;
+
+    Ok(())
+}
+
+/// List the role menus
+#[poise::command(
+slash_command,
+rename = "list",
+guild_only,
+required_permissions = "MANAGE_ROLES",
+on_error = "crate::commands::error_handler",
+ephemeral = true
+)]
+pub async fn rolemenu_list(ctx: Context<'_>) -> anyhow::Result<()> {
+    let menus = RoleMenus::find()
+        .order_by_asc(role_menus::Column::Id)
+        .all(&ctx.data().db).await?;
+    if menus.is_empty() {
+        ctx.say("There are no role menus, post one with /rolemenu create").await?;
+        return Ok(());
+    }
+
+    let mut lines = vec![];
+    for menu in &menus {
+        let roles = RoleMenuEntries::find()
+            .filter(role_menu_entries::Column::MenuId.eq(menu.id))
+            .order_by_asc(role_menu_entries::Column::Position)
+            .all(&ctx.data().db).await?
+            .iter()
+            .map(|entry| format!("<@&{}>", entry.role_id))
+            .collect::<Vec<_>>();
+        lines.push(format!("`#{}` **{}** ({:?}) https://discord.com/channels/{}/{}/{}\n{}",
+            menu.id, menu.title, menu.style, P2SR_SERVER, menu.channel_id, menu.message_id,
+            if roles.is_empty() { "*No roles yet*".to_string() } else { roles.join(" ") }));
+    }
+
+    let pages: Vec<String> = lines.chunks(10).map(|chunk| chunk.join("\n")).collect();
+    let pages: Vec<&str> = pages.iter().map(|page| page.as_str()).collect();
+    poise::builtins::paginate(ctx, &pages).await?;
+
+    Ok(())
+}
+
+/// Role menu buttons and select menus
+pub fn routes() -> Vec<Route> {
+    vec![
+        Route { name: BUTTON_PREFIX, handler: RouteHandler::Component(|ctx, data, component, args| Box::pin(async move {
+            toggle_role(ctx, data, component, args.get(0)?, args.get(1)?).await
+        })) },
+        Route { name: SELECT_PREFIX, handler: RouteHandler::Component(|ctx, data, component, args| Box::pin(async move {
+            select_roles(ctx, data, component, args.get(0)?).await
+        })) },
+    ]
+}
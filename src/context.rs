@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use anyhow::Context as _;
 use serenity::all::{ChannelId, ForumTagId, GuildId, RoleId};
 
@@ -15,9 +17,30 @@ pub struct ForumAutoCloseConfig {
     pub lock_on_close: bool,
 }
 
+#[derive(Clone)]
+pub struct ConfirmationConfig {
+    /// Names of the commands that ask for confirmation
+    pub commands: HashSet<String>,
+    /// Members with any of these roles skip confirmation
+    pub trusted_roles: HashSet<RoleId>,
+}
+
 pub struct Data {
     pub db: luma1_data::sea_orm::DatabaseConnection,
     pub forum_auto_close: Option<ForumAutoCloseConfig>,
+    /// Whether members get their roles back when they leave and rejoin
+    pub role_persistence: bool,
+    pub confirmation: Option<ConfirmationConfig>,
+    /// Channel ban appeals are posted to. Appeals go through the external form when unset
+    pub appeal_channel: Option<ChannelId>,
+    /// Staff forum that DMs to the bot are relayed to. DMs are ignored when unset
+    pub modmail_forum: Option<ChannelId>,
+    /// Staff channel user reports are sent to. Reporting is disabled when unset
+    pub report_channel: Option<ChannelId>,
+    /// Staff channel new members are announced in
+    pub join_log_channel: Option<ChannelId>,
+    /// Channel roles taken from role menus are logged in
+    pub role_log_channel: Option<ChannelId>,
 }
 pub type Context<'a> = poise::Context<'a, Data, anyhow::Error>;
 
@@ -30,13 +53,94 @@ impl Data {
 
         println!("Connecting to database at {}", database_url);
         let forum_auto_close = load_forum_auto_close_config()?;
+        let role_persistence = load_role_persistence_config()?;
+        let confirmation = load_confirmation_config()?;
+        let appeal_channel = load_appeal_channel_config()?;
+        let modmail_forum = load_modmail_forum_config()?;
+        let report_channel = load_report_channel_config()?;
+        let join_log_channel = load_join_log_channel_config()?;
+        let role_log_channel = load_role_log_channel_config()?;
+        let db = luma1_data::sea_orm::Database::connect(database_url).await?;
+        crate::entity::ensure_schema(&db).await?;
         Ok(Data {
-            db: luma1_data::sea_orm::Database::connect(database_url).await?,
+            db,
             forum_auto_close,
+            role_persistence,
+            confirmation,
+            appeal_channel,
+            modmail_forum,
+            report_channel,
+            join_log_channel,
+            role_log_channel,
         })
     }
 }
 
+fn load_role_persistence_config() -> anyhow::Result<bool> {
+    match std::env::var("ROLE_PERSISTENCE") {
+        Ok(val) => val
+            .parse::<bool>()
+            .context("ROLE_PERSISTENCE must be either true or false"),
+        Err(std::env::VarError::NotPresent) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn load_appeal_channel_config() -> anyhow::Result<Option<ChannelId>> {
+    match std::env::var("APPEAL_CHANNEL_ID") {
+        Ok(val) => val
+            .parse::<u64>()
+            .map(|channel_id| Some(ChannelId::new(channel_id)))
+            .context("APPEAL_CHANNEL_ID must be an integer Discord channel id"),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn load_modmail_forum_config() -> anyhow::Result<Option<ChannelId>> {
+    match std::env::var("MODMAIL_FORUM_ID") {
+        Ok(val) => val
+            .parse::<u64>()
+            .map(|channel_id| Some(ChannelId::new(channel_id)))
+            .context("MODMAIL_FORUM_ID must be an integer Discord channel id"),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn load_report_channel_config() -> anyhow::Result<Option<ChannelId>> {
+    match std::env::var("REPORT_CHANNEL_ID") {
+        Ok(val) => val
+            .parse::<u64>()
+            .map(|channel_id| Some(ChannelId::new(channel_id)))
+            .context("REPORT_CHANNEL_ID must be an integer Discord channel id"),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn load_join_log_channel_config() -> anyhow::Result<Option<ChannelId>> {
+    match std::env::var("JOIN_LOG_CHANNEL_ID") {
+        Ok(val) => val
+            .parse::<u64>()
+            .map(|channel_id| Some(ChannelId::new(channel_id)))
+            .context("JOIN_LOG_CHANNEL_ID must be an integer Discord channel id"),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn load_role_log_channel_config() -> anyhow::Result<Option<ChannelId>> {
+    match std::env::var("ROLE_LOG_CHANNEL_ID") {
+        Ok(val) => val
+            .parse::<u64>()
+            .map(|channel_id| Some(ChannelId::new(channel_id)))
+            .context("ROLE_LOG_CHANNEL_ID must be an integer Discord channel id"),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
 fn load_forum_auto_close_config() -> anyhow::Result<Option<ForumAutoCloseConfig>> {
     let channel_id = match std::env::var("FORUM_AUTO_CLOSE_CHANNEL_ID") {
         Ok(val) => Some(val),
@@ -83,3 +187,31 @@ fn load_forum_auto_close_config() -> anyhow::Result<Option<ForumAutoCloseConfig>
         ),
     }
 }
+
+fn load_confirmation_config() -> anyhow::Result<Option<ConfirmationConfig>> {
+    let commands = match std::env::var("CONFIRM_COMMANDS") {
+        Ok(val) => val,
+        Err(std::env::VarError::NotPresent) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let trusted_roles = match std::env::var("CONFIRM_TRUSTED_ROLES") {
+        Ok(val) => val
+            .split(',')
+            .map(str::trim)
+            .filter(|role_id| !role_id.is_empty())
+            .map(|role_id| role_id.parse::<u64>().map(RoleId::new))
+            .collect::<Result<HashSet<_>, _>>()
+            .context("CONFIRM_TRUSTED_ROLES must be a comma separated list of Discord role ids")?,
+        Err(std::env::VarError::NotPresent) => HashSet::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(Some(ConfirmationConfig {
+        commands: commands
+            .split(',')
+            .map(|command| command.trim().to_string())
+            .filter(|command| !command.is_empty())
+            .collect(),
+        trusted_roles,
+    }))
+}
@@ -0,0 +1,21 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// Two accounts a moderator believes belong to the same person.
+/// The lower user id is always stored first so each pair is only stored once.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_alt_links")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: i64,
+    #[sea_orm(primary_key, auto_increment = false, indexed)]
+    pub alt_user_id: i64,
+    pub linked_by: i64,
+    pub linked_at: DateTimeUtc,
+    pub reason: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
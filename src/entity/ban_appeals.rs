@@ -0,0 +1,38 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// Where an appeal is in the review process
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum AppealStatus {
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    #[sea_orm(string_value = "accepted")]
+    Accepted,
+    #[sea_orm(string_value = "denied")]
+    Denied,
+}
+
+/// A ban appeal submitted through the bot, along with the moderators' decision once one is made
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_ban_appeals")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub user_id: i64,
+    pub appeal: String,
+    pub submitted_at: DateTimeUtc,
+    /// The message in the appeal channel moderators review the appeal from
+    pub message_id: Option<i64>,
+    pub status: AppealStatus,
+    pub decided_by: Option<i64>,
+    pub decided_at: Option<DateTimeUtc>,
+    /// Message sent to the user along with the decision
+    pub response: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,22 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// One ban of a user, kept after it ends. `unbanned_at` is unset while the ban is still in place.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_ban_history")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub user_id: i64,
+    pub moderator_id: i64,
+    pub reason: Option<String>,
+    pub banned_at: DateTimeUtc,
+    pub unbanned_at: Option<DateTimeUtc>,
+    pub unbanned_by: Option<i64>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,23 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// One dunce of a user, kept after it ends. `ended_at` is unset while the dunce is still active.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_dunce_history")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub user_id: i64,
+    pub moderator_id: i64,
+    pub reason: Option<String>,
+    pub dunced_at: DateTimeUtc,
+    pub undunce_instant: DateTimeUtc,
+    pub ended_at: Option<DateTimeUtc>,
+    pub ended_by: Option<i64>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,64 @@
+//! Tables owned by L3ma itself.
+//!
+//! Shared Luma tables live in `luma1_data::entity`; anything only this bot needs is defined here
+//! and created on startup if it doesn't exist yet, along with an index for every column marked
+//! `#[sea_orm(indexed)]`.
+//!
+//! Creation is `IF NOT EXISTS` only: adding, renaming or retyping a column on a table that is
+//! already deployed won't happen on its own and needs a migration run against the database.
+
+pub mod prelude;
+pub mod alt_links;
+pub mod ban_appeals;
+pub mod ban_history;
+pub mod dunce_history;
+pub mod modmail_blocks;
+pub mod modmail_threads;
+pub mod notes;
+pub mod persisted_roles;
+pub mod reminders;
+pub mod report_reporters;
+pub mod reports;
+pub mod role_menu_entries;
+pub mod role_menus;
+pub mod slowmode_reverts;
+pub mod warnings;
+
+use luma1_data::sea_orm::{ConnectionTrait, DatabaseConnection, EntityTrait, Schema};
+
+use prelude::*;
+
+async fn create_table<E: EntityTrait>(db: &DatabaseConnection, entity: E) -> anyhow::Result<()> {
+    let backend = db.get_database_backend();
+    let mut statement = Schema::new(backend).create_table_from_entity(entity);
+    statement.if_not_exists();
+    db.execute(backend.build(&statement)).await?;
+
+    for mut index in Schema::new(backend).create_index_from_entity(entity) {
+        index.if_not_exists();
+        db.execute(backend.build(&index)).await?;
+    }
+
+    Ok(())
+}
+
+/// Create any L3ma-owned tables that are missing from the database
+pub async fn ensure_schema(db: &DatabaseConnection) -> anyhow::Result<()> {
+    create_table(db, SlowmodeReverts).await?;
+    create_table(db, DunceHistory).await?;
+    create_table(db, PersistedRoles).await?;
+    create_table(db, BanAppeals).await?;
+    create_table(db, ModmailThreads).await?;
+    create_table(db, ModmailBlocks).await?;
+    create_table(db, Warnings).await?;
+    create_table(db, Reports).await?;
+    create_table(db, ReportReporters).await?;
+    create_table(db, Notes).await?;
+    create_table(db, AltLinks).await?;
+    create_table(db, Reminders).await?;
+    create_table(db, RoleMenus).await?;
+    create_table(db, RoleMenuEntries).await?;
+    create_table(db, BanHistory).await?;
+
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// A user whose DMs are no longer relayed to the staff
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_modmail_blocks")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: i64,
+    pub blocked_by: i64,
+    pub blocked_at: DateTimeUtc,
+    pub reason: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
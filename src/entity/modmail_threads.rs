@@ -0,0 +1,23 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// A modmail conversation between a user and the staff. `closed_at` is unset while it is still open.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_modmail_threads")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub user_id: i64,
+    /// The staff forum post the conversation is relayed to
+    #[sea_orm(indexed)]
+    pub thread_id: i64,
+    pub opened_at: DateTimeUtc,
+    pub closed_at: Option<DateTimeUtc>,
+    pub closed_by: Option<i64>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
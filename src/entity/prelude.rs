@@ -0,0 +1,15 @@
+pub use super::alt_links::Entity as AltLinks;
+pub use super::ban_appeals::Entity as BanAppeals;
+pub use super::ban_history::Entity as BanHistory;
+pub use super::dunce_history::Entity as DunceHistory;
+pub use super::modmail_blocks::Entity as ModmailBlocks;
+pub use super::modmail_threads::Entity as ModmailThreads;
+pub use super::notes::Entity as Notes;
+pub use super::persisted_roles::Entity as PersistedRoles;
+pub use super::reminders::Entity as Reminders;
+pub use super::report_reporters::Entity as ReportReporters;
+pub use super::reports::Entity as Reports;
+pub use super::role_menu_entries::Entity as RoleMenuEntries;
+pub use super::role_menus::Entity as RoleMenus;
+pub use super::slowmode_reverts::Entity as SlowmodeReverts;
+pub use super::warnings::Entity as Warnings;
@@ -0,0 +1,23 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// A reminder a user asked for, deleted once it has been delivered
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_reminders")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub user_id: i64,
+    /// Channel to post the reminder in, or unset to DM it
+    pub channel_id: Option<i64>,
+    pub message: String,
+    pub created_at: DateTimeUtc,
+    #[sea_orm(indexed)]
+    pub remind_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
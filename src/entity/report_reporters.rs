@@ -0,0 +1,19 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// A user who filed a report, and why
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_report_reporters")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub report_id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub reporter_id: i64,
+    pub reason: Option<String>,
+    pub reported_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,47 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// Where a report is in the review process
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum ReportStatus {
+    #[sea_orm(string_value = "open")]
+    Open,
+    #[sea_orm(string_value = "actioned")]
+    Actioned,
+    #[sea_orm(string_value = "dismissed")]
+    Dismissed,
+}
+
+/// A user or message reported to the staff. Everyone who reports the same thing while it is open
+/// is added to one report, see `report_reporters`.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_reports")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub reported_user_id: i64,
+    /// Set when a specific message was reported
+    pub channel_id: Option<i64>,
+    #[sea_orm(indexed)]
+    pub message_id: Option<i64>,
+    /// Snapshot of the reported message, kept in case it is deleted
+    pub content: Option<String>,
+    /// Attachment URLs of the reported message, one per line
+    pub attachments: Option<String>,
+    pub message_deleted: bool,
+    /// The alert in the report channel moderators act from
+    pub alert_message_id: Option<i64>,
+    pub status: ReportStatus,
+    /// What moderators did about the report
+    pub resolution: Option<String>,
+    pub created_at: DateTimeUtc,
+    pub resolved_by: Option<i64>,
+    pub resolved_at: Option<DateTimeUtc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
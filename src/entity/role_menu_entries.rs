@@ -0,0 +1,23 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// A role offered by a role menu
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_role_menu_entries")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub menu_id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub role_id: i64,
+    pub label: String,
+    /// Required for reaction menus, where it is what members react with
+    pub emoji: Option<String>,
+    pub description: Option<String>,
+    /// Entries are shown in the order they were added
+    pub position: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
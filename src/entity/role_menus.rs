@@ -0,0 +1,40 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// How members pick roles from a menu
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, poise::ChoiceParameter)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum RoleMenuStyle {
+    #[sea_orm(string_value = "buttons")]
+    Buttons,
+    #[sea_orm(string_value = "select")]
+    #[name = "Select menu"]
+    Select,
+    #[sea_orm(string_value = "reactions")]
+    Reactions,
+}
+
+/// A message members can give themselves roles from. The roles on offer are in `role_menu_entries`.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_role_menus")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub channel_id: i64,
+    #[sea_orm(indexed)]
+    pub message_id: i64,
+    pub style: RoleMenuStyle,
+    pub title: String,
+    pub description: Option<String>,
+    /// Most roles from this menu a member can have at once
+    pub max_roles: Option<i32>,
+    /// Role members need before they can use this menu
+    pub required_role_id: Option<i64>,
+    pub created_by: i64,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,18 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// A channel whose slowmode should be put back to `previous_rate_limit` at `revert_instant`
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_slowmode_reverts")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub channel_id: i64,
+    pub previous_rate_limit: i32,
+    #[sea_orm(indexed)]
+    pub revert_instant: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
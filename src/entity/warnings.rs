@@ -0,0 +1,20 @@
+use luma1_data::sea_orm;
+use sea_orm::entity::prelude::*;
+
+/// A formal warning given to a user by a moderator
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "l3ma_warnings")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub user_id: i64,
+    pub moderator_id: i64,
+    pub reason: String,
+    pub warned_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
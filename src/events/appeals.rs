@@ -0,0 +1,285 @@
+use chrono::Utc;
+use serenity::all::{ButtonStyle, Color, ComponentInteraction, CreateActionRow, CreateButton, CreateEmbed, CreateEmbedAuthor, CreateEmbedFooter, CreateInputText, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateMessage, CreateModal, CreateThread, EditInteractionResponse, InputTextStyle, Member, Mentionable, Message, MessageId, ModalInteraction, Timestamp, User, UserId};
+use serenity::prelude::Context as SerenityContext;
+
+use luma1_data::sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, Set};
+
+use crate::commands;
+use crate::context::{Data, P2SR_SERVER};
+use crate::entity::ban_appeals::{self, AppealStatus};
+use crate::entity::prelude::*;
+use super::{ephemeral_message, input_value, is_banned};
+use super::router::custom_id;
+
+/// Button in a ban DM that starts an appeal
+pub const APPEAL_START_ID: &str = "appeal-start";
+pub const APPEAL_SUBMIT_ID: &str = "appeal-submit";
+const APPEAL_TEXT_ID: &str = "appeal-text";
+const RESPONSE_TEXT_ID: &str = "appeal-response";
+
+// Moderator buttons and modals carry the appeal id
+pub const ACCEPT_PREFIX: &str = "appeal-accept";
+pub const DENY_PREFIX: &str = "appeal-deny";
+pub const DISCUSS_PREFIX: &str = "appeal-discuss";
+pub const ACCEPT_SUBMIT_PREFIX: &str = "appeal-accept-submit";
+pub const DENY_SUBMIT_PREFIX: &str = "appeal-deny-submit";
+
+/// The button banned users press to appeal their ban
+pub fn appeal_button() -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new(custom_id(APPEAL_START_ID, &[])).label("Appeal").style(ButtonStyle::Primary)
+    ])
+}
+
+async fn find_pending_appeal(data: &Data, user_id: UserId) -> anyhow::Result<Option<ban_appeals::Model>> {
+    Ok(BanAppeals::find()
+        .filter(ban_appeals::Column::UserId.eq(user_id.get() as i64))
+        .filter(ban_appeals::Column::Status.eq(AppealStatus::Pending))
+        .one(&data.db).await?)
+}
+
+/// Point a banned user who DMs the bot at the appeal button.
+/// Returns whether the message was handled as part of an appeal.
+pub async fn offer_appeal(ctx: &SerenityContext, data: &Data, message: &Message) -> anyhow::Result<bool> {
+    if data.appeal_channel.is_none() || !is_banned(ctx, message.author.id).await? {
+        return Ok(false);
+    }
+
+    let reply = match find_pending_appeal(data, message.author.id).await? {
+        Some(_) => CreateMessage::new()
+            .content("Your appeal is waiting for a decision from the moderators, you'll get a DM once one is made"),
+        None => CreateMessage::new()
+            .content("You are banned from the Portal 2 Speedrun Server. Press the button below to appeal your ban")
+            .components(vec![appeal_button()])
+    };
+    message.channel_id.send_message(&ctx.http, reply).await?;
+
+    Ok(true)
+}
+
+/// Show a banned user the appeal form
+pub async fn start_appeal(ctx: &SerenityContext, data: &Data, component: &ComponentInteraction) -> anyhow::Result<()> {
+    if data.appeal_channel.is_none() {
+        component.create_response(&ctx.http, ephemeral_message("Appeals aren't open right now, please try again later")).await?;
+        return Ok(());
+    }
+    if !is_banned(ctx, component.user.id).await? {
+        component.create_response(&ctx.http, ephemeral_message("You are not banned, there is nothing to appeal")).await?;
+        return Ok(());
+    }
+    if find_pending_appeal(data, component.user.id).await?.is_some() {
+        component.create_response(&ctx.http, ephemeral_message("Your appeal is already waiting for a decision from the moderators")).await?;
+        return Ok(());
+    }
+
+    let modal = CreateModal::new(custom_id(APPEAL_SUBMIT_ID, &[]), "Appeal your ban")
+        .components(vec![CreateActionRow::InputText(
+            CreateInputText::new(InputTextStyle::Paragraph, "Why should you be unbanned?", APPEAL_TEXT_ID)
+                .min_length(20)
+                .max_length(2000)
+        )]);
+    component.create_response(&ctx.http, CreateInteractionResponse::Modal(modal)).await?;
+
+    Ok(())
+}
+
+/// Record a submitted appeal and post it for the moderators to review
+pub async fn submit_appeal(ctx: &SerenityContext, data: &Data, modal: &ModalInteraction) -> anyhow::Result<()> {
+    let Some(appeal_channel) = data.appeal_channel else {
+        modal.create_response(&ctx.http, ephemeral_message("Appeals aren't open right now, please try again later")).await?;
+        return Ok(());
+    };
+    // The user may have been unbanned or submitted from another DM while the form was open
+    if !is_banned(ctx, modal.user.id).await? || find_pending_appeal(data, modal.user.id).await?.is_some() {
+        modal.create_response(&ctx.http, ephemeral_message("This appeal can no longer be submitted")).await?;
+        return Ok(());
+    }
+
+    let appeal = input_value(&modal.data.components, APPEAL_TEXT_ID).unwrap_or_default();
+    let submitted_at = Utc::now();
+
+    let record = ban_appeals::ActiveModel {
+        user_id: Set(modal.user.id.get() as i64),
+        appeal: Set(appeal.clone()),
+        submitted_at: Set(submitted_at),
+        status: Set(AppealStatus::Pending),
+        ..Default::default()
+    }.insert(&data.db).await?;
+
+    let embed = CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .title("Ban appeal")
+        .author(CreateEmbedAuthor::new(&modal.user.name).icon_url(modal.user.face()))
+        .description(appeal)
+        .field("User", format!("{} ({})", modal.user.mention(), modal.user.id), false)
+        .footer(CreateEmbedFooter::new(format!("Appeal #{}", record.id)))
+        .timestamp(Timestamp::now());
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(custom_id(ACCEPT_PREFIX, &[&record.id])).label("Accept").style(ButtonStyle::Success),
+        CreateButton::new(custom_id(DENY_PREFIX, &[&record.id])).label("Deny").style(ButtonStyle::Danger),
+        CreateButton::new(custom_id(DISCUSS_PREFIX, &[&record.id])).label("Discuss").style(ButtonStyle::Secondary)
+    ]);
+    let message = appeal_channel.send_message(&ctx.http, CreateMessage::new()
+        .embed(embed)
+        .components(vec![buttons])
+    ).await?;
+
+    let mut record = record.into_active_model();
+    record.message_id = Set(Some(message.id.get() as i64));
+    record.update(&data.db).await?;
+
+    modal.create_response(&ctx.http, CreateInteractionResponse::Message(CreateInteractionResponseMessage::new()
+        .content("Your appeal was sent to the moderators, you'll get a DM once they make a decision")
+    )).await?;
+
+    Ok(())
+}
+
+/// Only moderators who could ban the user themselves get to decide their appeal
+fn can_decide(member: Option<&Member>) -> bool {
+    member.and_then(|member| member.permissions).is_some_and(|permissions| permissions.ban_members())
+}
+
+/// Ask the moderator for a message to send along with their decision
+pub async fn open_decision(ctx: &SerenityContext, data: &Data, component: &ComponentInteraction, appeal_id: i32, accept: bool) -> anyhow::Result<()> {
+    if !can_decide(component.member.as_ref()) {
+        component.create_response(&ctx.http, ephemeral_message("You need the Ban Members permission to decide appeals")).await?;
+        return Ok(());
+    }
+
+    let pending = BanAppeals::find_by_id(appeal_id).one(&data.db).await?
+        .is_some_and(|appeal| appeal.status == AppealStatus::Pending);
+    if !pending {
+        component.create_response(&ctx.http, ephemeral_message("This appeal was already decided")).await?;
+        return Ok(());
+    }
+
+    let (prefix, title) = match accept {
+        true => (ACCEPT_SUBMIT_PREFIX, "Accept appeal"),
+        false => (DENY_SUBMIT_PREFIX, "Deny appeal")
+    };
+    let modal = CreateModal::new(custom_id(prefix, &[&appeal_id]), title)
+        .components(vec![CreateActionRow::InputText(
+            CreateInputText::new(InputTextStyle::Paragraph, "Message to the user (optional)", RESPONSE_TEXT_ID)
+                .max_length(1000)
+                .required(false)
+        )]);
+    component.create_response(&ctx.http, CreateInteractionResponse::Modal(modal)).await?;
+
+    Ok(())
+}
+
+/// Open a thread on the appeal for moderators to talk it over
+pub async fn discuss_appeal(ctx: &SerenityContext, component: &ComponentInteraction) -> anyhow::Result<()> {
+    let thread = match &component.message.thread {
+        Some(thread) => thread.id,
+        None => component.channel_id.create_thread_from_message(
+            &ctx.http,
+            component.message.id,
+            CreateThread::new(format!("Appeal discussion - {}", component.message.embeds.first()
+                .and_then(|embed| embed.author.as_ref())
+                .map(|author| author.name.as_str())
+                .unwrap_or("unknown user")))
+        ).await?.id
+    };
+
+    component.create_response(&ctx.http, ephemeral_message(format!("Discuss this appeal in {}", thread.mention()))).await?;
+
+    Ok(())
+}
+
+/// Carry out a moderator's decision on an appeal
+pub async fn decide_appeal(ctx: &SerenityContext, data: &Data, modal: &ModalInteraction, appeal_id: i32, accept: bool) -> anyhow::Result<()> {
+    if !can_decide(modal.member.as_ref()) {
+        modal.create_response(&ctx.http, ephemeral_message("You need the Ban Members permission to decide appeals")).await?;
+        return Ok(());
+    }
+    // Unbanning, DMing and logging can take longer than Discord waits for a response
+    modal.create_response(&ctx.http, CreateInteractionResponse::Acknowledge).await?;
+    let reply = |content: &str| modal.create_followup(&ctx.http,
+        CreateInteractionResponseFollowup::new().content(content).ephemeral(true));
+
+    let Some(appeal) = BanAppeals::find_by_id(appeal_id).one(&data.db).await? else {
+        reply("This appeal no longer exists").await?;
+        return Ok(());
+    };
+    if appeal.status != AppealStatus::Pending {
+        reply("This appeal was already decided").await?;
+        return Ok(());
+    }
+
+    let user_id = UserId::new(appeal.user_id as u64);
+    let response = input_value(&modal.data.components, RESPONSE_TEXT_ID);
+
+    if accept && let Err(e) = P2SR_SERVER.unban(&ctx.http, user_id).await {
+        reply(&format!("Failed to unban user, the appeal is still pending:```diff\n- {:#}\n```", anyhow::Error::new(e))).await?;
+        return Ok(());
+    }
+
+    let appeal_message_id = appeal.message_id.map(|id| MessageId::new(id as u64));
+    let mut record = appeal.into_active_model();
+    record.status = Set(if accept { AppealStatus::Accepted } else { AppealStatus::Denied });
+    record.decided_by = Set(Some(modal.user.id.get() as i64));
+    record.decided_at = Set(Some(Utc::now()));
+    record.response = Set(response.clone());
+    record.update(&data.db).await?;
+
+    let decision = if accept { "Accepted" } else { "Denied" };
+    let mut errors: Vec<String> = vec![];
+
+    if accept && let Err(e) = commands::end_ban_records(&data.db, user_id, modal.user.id).await {
+        errors.push(format!("{:#}", e.context("Could not record the unban")));
+    }
+
+    // Let the user know, they may have closed their DMs since appealing
+    if send_decision_dm(ctx, user_id, accept, response.as_deref()).await.is_err() {
+        errors.push("Unable to DM User".to_string());
+    }
+
+    let log_result = commands::send_mod_action_log(&ctx.http, modal.user.clone(), |embed| {
+        let embed = embed.description(format!("{} ban appeal #{} from <@{}> ({}){}",
+            decision, appeal_id, user_id, user_id, if accept { ", they were unbanned" } else { "" }));
+        match &response {
+            Some(response) => embed.field("Message to the user", response, false),
+            None => embed
+        }
+    }).await;
+    if let Err(e) = log_result {
+        errors.push(format!("{:#}", e));
+    }
+
+    // Replace the buttons on the appeal with the outcome
+    let mut outcome = format!("{} by {}", decision, modal.user.mention());
+    if !errors.is_empty() {
+        outcome.push_str(&format!("\n```diff\n- {}\n```", errors.join("\n- ")));
+    }
+    let embed = modal.message.as_ref()
+        .filter(|message| Some(message.id) == appeal_message_id)
+        .and_then(|message| message.embeds.first().cloned())
+        .map(CreateEmbed::from)
+        .unwrap_or_default()
+        .field("Decision", outcome, false);
+    modal.edit_response(&ctx.http, EditInteractionResponse::new().embed(embed).components(vec![])).await?;
+
+    Ok(())
+}
+
+async fn send_decision_dm(ctx: &SerenityContext, user_id: UserId, accept: bool, response: Option<&str>) -> anyhow::Result<()> {
+    let mut embed = CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .title(if accept { "Your ban appeal was accepted" } else { "Your ban appeal was denied" })
+        .footer(CreateEmbedFooter::new("Portal 2 Speedrun Server"))
+        .timestamp(Timestamp::now());
+    if accept {
+        embed = embed.description("You have been unbanned and can rejoin the server");
+    }
+    if let Some(response) = response {
+        embed = embed.field("Message from the moderators", response, false);
+    }
+
+    let user: User = user_id.to_user(&ctx.http).await?;
+    let channel = user.create_dm_channel(&ctx.http).await?;
+    channel.send_message(&ctx.http, CreateMessage::new().embed(embed)).await?;
+
+    Ok(())
+}
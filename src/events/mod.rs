@@ -1,10 +1,43 @@
-use serenity::all::{Channel, ChannelType, GuildChannel, Member, Reaction, ReactionType};
+use serenity::all::{ActionRow, ActionRowComponent, Ban, Channel, ChannelType, CreateInteractionResponse, CreateInteractionResponseMessage, GuildChannel, GuildId, HttpError, Interaction, LightMethod, Member, Message, Reaction, ReactionType, Request, Route, StatusCode, User, UserId};
 use serenity::builder::EditThread;
 use serenity::prelude::{EventHandler};
 use serenity::prelude::Context as SerenityContext;
-use crate::context::Data;
+use crate::context::{Data, P2SR_SERVER};
 
+pub mod appeals;
 mod moderation;
+mod modmail;
+pub mod reports;
+pub mod role_menus;
+pub mod router;
+
+/// Read the value of a text input from a submitted modal
+fn input_value(components: &[ActionRow], custom_id: &str) -> Option<String> {
+    components.iter()
+        .flat_map(|row| &row.components)
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(input) if input.custom_id == custom_id => input.value.clone(),
+            _ => None
+        })
+        .filter(|value| !value.trim().is_empty())
+}
+
+fn ephemeral_message(content: impl Into<String>) -> CreateInteractionResponse {
+    CreateInteractionResponse::Message(CreateInteractionResponseMessage::new()
+        .content(content)
+        .ephemeral(true))
+}
+
+/// Check whether a user is currently banned from P2SR
+async fn is_banned(ctx: &SerenityContext, user_id: UserId) -> anyhow::Result<bool> {
+    let request = Request::new(Route::GuildBan { guild_id: P2SR_SERVER, user_id }, LightMethod::Get);
+    match ctx.http.fire::<Ban>(request).await {
+        Ok(_) => Ok(true),
+        Err(serenity::Error::Http(HttpError::UnsuccessfulRequest(response)))
+            if response.status_code == StatusCode::NOT_FOUND => Ok(false),
+        Err(e) => Err(anyhow::Error::new(e).context("Could not check whether the user is banned"))
+    }
+}
 
 pub struct Handler {
     pub(crate) data: Data
@@ -13,7 +46,59 @@ pub struct Handler {
 #[serenity::async_trait]
 impl EventHandler for Handler {
     async fn guild_member_addition(&self, ctx: SerenityContext, new_member: Member) {
-        if let Err(e) = moderation::ensure_dunced(ctx, &self.data, new_member).await {
+        if let Err(e) = moderation::post_join_log(&ctx, &self.data, &new_member).await {
+            eprintln!("Encountered error while handling event: {:?}", e);
+        }
+        if let Err(e) = moderation::alert_linked_join(&ctx, &self.data, &new_member).await {
+            eprintln!("Encountered error while handling event: {:?}", e);
+        }
+
+        match moderation::ensure_dunced(&ctx, &self.data, &new_member).await {
+            Ok(false) if self.data.role_persistence => {
+                if let Err(e) = moderation::restore_persisted_roles(&ctx, &self.data, &new_member).await {
+                    eprintln!("Encountered error while handling event: {:?}", e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Encountered error while handling event: {:?}", e)
+        }
+    }
+
+    async fn guild_member_removal(
+        &self,
+        _ctx: SerenityContext,
+        guild_id: GuildId,
+        _user: User,
+        member_data_if_available: Option<Member>,
+    ) {
+        if !self.data.role_persistence {
+            return;
+        }
+
+        if let Err(e) = moderation::persist_roles(&self.data, guild_id, member_data_if_available).await {
+            eprintln!("Encountered error while handling event: {:?}", e);
+        }
+    }
+
+    async fn message(&self, ctx: SerenityContext, new_message: Message) {
+        // Only direct messages from users are handled here
+        if new_message.guild_id.is_some() || new_message.author.bot {
+            return;
+        }
+
+        // Banned users are pointed at their appeal, everyone else reaches the staff through modmail
+        let result = match appeals::offer_appeal(&ctx, &self.data, &new_message).await {
+            Ok(true) => Ok(()),
+            Ok(false) => modmail::relay_dm(&ctx, &self.data, &new_message).await,
+            Err(e) => Err(e)
+        };
+        if let Err(e) = result {
+            eprintln!("Encountered error while handling event: {:?}", e);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: SerenityContext, interaction: Interaction) {
+        if let Err(e) = router::dispatch(&ctx, &self.data, &interaction).await {
             eprintln!("Encountered error while handling event: {:?}", e);
         }
     }
@@ -39,13 +124,13 @@ impl EventHandler for Handler {
             return;
         }
 
-        if !new.applied_tags.iter().any(|tag| *tag == config.close_tag_id) {
+        if !new.applied_tags.contains(&config.close_tag_id) {
             return;
         }
 
         if old
             .as_ref()
-            .is_some_and(|previous| previous.applied_tags.iter().any(|tag| *tag == config.close_tag_id))
+            .is_some_and(|previous| previous.applied_tags.contains(&config.close_tag_id))
         {
             return;
         }
@@ -82,6 +167,10 @@ impl EventHandler for Handler {
     }
 
     async fn reaction_add(&self, ctx: SerenityContext, reaction: Reaction) {
+        if let Err(e) = role_menus::handle_reaction(&ctx, &self.data, &reaction, true).await {
+            eprintln!("Encountered error while handling event: {:?}", e);
+        }
+
         let Some(config) = &self.data.forum_auto_close else {
             return;
         };
@@ -148,4 +237,10 @@ impl EventHandler for Handler {
             );
         }
     }
+
+    async fn reaction_remove(&self, ctx: SerenityContext, reaction: Reaction) {
+        if let Err(e) = role_menus::handle_reaction(&ctx, &self.data, &reaction, false).await {
+            eprintln!("Encountered error while handling event: {:?}", e);
+        }
+    }
 }
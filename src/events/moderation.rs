@@ -1,22 +1,216 @@
-use serenity::all::Member;
+use chrono::Utc;
+use serenity::all::{Color, CreateEmbed, CreateEmbedAuthor, CreateMessage, GuildId, Member, Mentionable, RoleId, Timestamp, User};
 use serenity::prelude::Context as SerenityContext;
+use crate::commands;
 use crate::context::{Data, P2SR_SERVER, P2SR_DUNCE_ROLE};
 
 use luma1_data::entity::prelude::*;
-use luma1_data::sea_orm::EntityTrait;
+use luma1_data::sea_orm::{ColumnTrait, EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter, Set, TransactionTrait};
+use crate::entity::prelude::*;
 
-pub async fn ensure_dunced(ctx: SerenityContext, data: &Data, member: Member) -> anyhow::Result<()> {
+/// Re-apply or finish a dunce for a member who rejoined.
+/// Returns whether the member had a dunce recorded.
+pub async fn ensure_dunced(ctx: &SerenityContext, data: &Data, member: &Member) -> anyhow::Result<bool> {
     // We only perform checks in P2SR
     if member.guild_id != P2SR_SERVER {
-        return Ok(());
+        return Ok(false);
     }
 
     // Check to see if the member is dunced
-    if DunceInstants::find_by_id(member.user.id.get() as i64)
-        .one(&data.db).await?.is_some() {
+    let Some(dunce_instant) = DunceInstants::find_by_id(member.user.id.get() as i64)
+        .one(&data.db).await? else {
+        return Ok(false);
+    };
+
+    let bot_user: User = ctx.cache.current_user().clone().into();
+    let undunce_time = dunce_instant.undunce_instant;
+
+    if undunce_time > Utc::now() {
         // Give the user back their dunce role
         member.add_role(&ctx.http, P2SR_DUNCE_ROLE).await?;
+
+        commands::send_mod_action_log(&ctx.http, bot_user, |embed| {
+            embed.description(format!("{} ({}) rejoined while dunced, the dunce role was reapplied", member.mention(), member.user.id))
+                .field("Remaining", format!("Undunce <t:{}:R>", undunce_time.timestamp()), true)
+                .field("Expires", format!("<t:{}:f>", undunce_time.timestamp()), true)
+        }).await?;
+
+        return Ok(true);
+    }
+
+    // The dunce expired while they were away, so finish undunceing them
+    let guild_roles = P2SR_SERVER.roles(&ctx.http).await?;
+    let stored_roles: Vec<RoleId> = DunceStoredRoles::find()
+        .filter(luma1_data::entity::dunce_stored_roles::Column::UserId.eq(member.user.id.get() as i64))
+        .all(&data.db).await?
+        .iter().map(|r| RoleId::new(r.role_id as u64))
+        .filter(|role_id| guild_roles.contains_key(role_id))
+        .collect();
+
+    commands::swap_roles(&ctx.http, member, &[], &stored_roles).await?;
+
+    // The transaction only covers the database, never requests to Discord
+    let db_result: anyhow::Result<()> = async {
+        let txn = data.db.begin().await?;
+        DunceInstants::delete(dunce_instant.into_active_model())
+            .exec(&txn).await?;
+        DunceStoredRoles::delete_many()
+            .filter(luma1_data::entity::dunce_stored_roles::Column::UserId.eq(member.user.id.get() as i64))
+            .exec(&txn).await?;
+        commands::end_dunce_records(&txn, member.user.id, bot_user.id).await?;
+
+        txn.commit().await?;
+        Ok(())
+    }.await;
+    if let Err(e) = db_result {
+        // The undunce was never recorded, so take the restored roles away again for the next rejoin to retry
+        if let Err(swap_error) = commands::swap_roles(&ctx.http, member, &stored_roles, &[]).await {
+            return Err(e.context(format!("Failed to take back restored roles: {:#}", swap_error)));
+        }
+        return Err(e);
+    }
+
+    commands::send_mod_action_log(&ctx.http, bot_user, |embed| {
+        embed.description(format!("Undunced {} ({}) on rejoin, their dunce expired while they were away", member.mention(), member.user.id))
+            .field("Restored roles", stored_roles.len().to_string(), true)
+    }).await?;
+
+    Ok(true)
+}
+
+/// Remember the roles of a member who left, so they can be given back if they rejoin
+pub async fn persist_roles(data: &Data, guild_id: GuildId, member: Option<Member>) -> anyhow::Result<()> {
+    if guild_id != P2SR_SERVER {
+        return Ok(());
     }
 
+    // Roles are only known if the member was cached
+    let Some(member) = member else {
+        return Ok(());
+    };
+
+    // Dunced members already have their roles stored with their dunce
+    if member.roles.contains(&P2SR_DUNCE_ROLE) {
+        return Ok(());
+    }
+
+    let user_id = member.user.id.get() as i64;
+    let txn = data.db.begin().await?;
+    PersistedRoles::delete_many()
+        .filter(crate::entity::persisted_roles::Column::UserId.eq(user_id))
+        .exec(&txn).await?;
+    PersistedRoles::insert_many(member.roles.iter().map(|role_id| {
+        crate::entity::persisted_roles::ActiveModel {
+            user_id: Set(user_id),
+            role_id: Set((*role_id).into())
+        }
+    })).on_empty_do_nothing().exec(&txn).await?;
+    txn.commit().await?;
+
+    Ok(())
+}
+
+/// Give a rejoining member back the roles they had when they left
+pub async fn restore_persisted_roles(ctx: &SerenityContext, data: &Data, member: &Member) -> anyhow::Result<()> {
+    if member.guild_id != P2SR_SERVER {
+        return Ok(());
+    }
+
+    let user_id = member.user.id.get() as i64;
+    let persisted_roles = PersistedRoles::find()
+        .filter(crate::entity::persisted_roles::Column::UserId.eq(user_id))
+        .all(&data.db).await?;
+    if persisted_roles.is_empty() {
+        return Ok(());
+    }
+
+    // Roles can't be given back if they were deleted, or if Discord manages them
+    let guild_roles = P2SR_SERVER.roles(&ctx.http).await?;
+    let roles: Vec<RoleId> = persisted_roles.iter()
+        .map(|r| RoleId::new(r.role_id as u64))
+        .filter(|role_id| guild_roles.get(role_id).is_some_and(|role| !role.managed))
+        .collect();
+
+    for role_id in &roles {
+        if let Err(e) = member.add_role(&ctx.http, *role_id).await {
+            eprintln!("Failed to restore role {} to {}: {:?}", role_id, member.user.id, e);
+        }
+    }
+
+    PersistedRoles::delete_many()
+        .filter(crate::entity::persisted_roles::Column::UserId.eq(user_id))
+        .exec(&data.db).await?;
+
+    Ok(())
+}
+
+/// Announce a new member in the join log, along with anything staff should know about them
+pub async fn post_join_log(ctx: &SerenityContext, data: &Data, member: &Member) -> anyhow::Result<()> {
+    let Some(join_log_channel) = data.join_log_channel else {
+        return Ok(());
+    };
+    if member.guild_id != P2SR_SERVER {
+        return Ok(());
+    }
+
+    let user_id = member.user.id.get() as i64;
+    let dunces = DunceHistory::find()
+        .filter(crate::entity::dunce_history::Column::UserId.eq(user_id))
+        .count(&data.db).await?;
+    let warnings = Warnings::find()
+        .filter(crate::entity::warnings::Column::UserId.eq(user_id))
+        .count(&data.db).await?;
+    let notes = commands::user_notes(&data.db, member.user.id).await?;
+    let linked = commands::linked_accounts(&data.db, member.user.id).await?;
+
+    let mut embed = CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .author(CreateEmbedAuthor::new(&member.user.name).icon_url(member.user.face()))
+        .description(format!("{} ({}) joined", member.mention(), member.user.id))
+        .field("Account created", format!("<t:{}:R>", member.user.id.created_at().unix_timestamp()), true)
+        .field("Past dunces", dunces.to_string(), true)
+        .field("Warnings", warnings.to_string(), true)
+        .timestamp(Timestamp::now());
+    if !linked.is_empty() {
+        let accounts: Vec<String> = linked.iter().map(|id| format!("{} ({})", id.mention(), id)).collect();
+        embed = embed.field("Linked accounts", commands::join_within(&accounts, "\n", 1024), false);
+    }
+    if !notes.is_empty() {
+        embed = embed.field(format!("Notes ({})", notes.len()), commands::summarize_notes(&notes), false);
+    }
+
+    join_log_channel.send_message(&ctx.http, CreateMessage::new().embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Warn staff when an account linked to a banned or dunced user joins
+pub async fn alert_linked_join(ctx: &SerenityContext, data: &Data, member: &Member) -> anyhow::Result<()> {
+    if member.guild_id != P2SR_SERVER {
+        return Ok(());
+    }
+
+    let mut flagged: Vec<String> = vec![];
+    for linked_id in commands::linked_accounts(&data.db, member.user.id).await? {
+        // A failed ban check leaves the ban unknown, the other accounts are still worth checking
+        let banned = super::is_banned(ctx, linked_id).await
+            .inspect_err(|e| eprintln!("Failed to check whether {} is banned: {:?}", linked_id, e))
+            .ok();
+        if banned == Some(true) {
+            flagged.push(format!("{} ({}) is banned", linked_id.mention(), linked_id));
+        } else if let Some(dunce_instant) = DunceInstants::find_by_id(linked_id.get() as i64).one(&data.db).await? {
+            flagged.push(format!("{} ({}) is dunced until <t:{}:f>", linked_id.mention(), linked_id, dunce_instant.undunce_instant.timestamp()));
+        }
+    }
+    if flagged.is_empty() {
+        return Ok(());
+    }
+
+    let bot_user: User = ctx.cache.current_user().clone().into();
+    commands::send_mod_action_log(&ctx.http, bot_user, |embed| {
+        embed.description(format!("⚠️ {} ({}) joined and is linked to moderated accounts, this may be evasion", member.mention(), member.user.id))
+            .field("Linked accounts", commands::join_within(&flagged, "\n", 1024), false)
+    }).await?;
+
     Ok(())
-}
\ No newline at end of file
+}
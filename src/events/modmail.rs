@@ -0,0 +1,133 @@
+use chrono::Utc;
+use serenity::all::{ChannelId, Color, CreateEmbed, CreateEmbedAuthor, CreateForumPost, CreateMessage, HttpError, Message, ReactionType, Timestamp, User};
+use serenity::prelude::Context as SerenityContext;
+use tokio::sync::Mutex;
+
+use luma1_data::sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, Set, sea_query};
+
+use crate::context::Data;
+use crate::entity::modmail_threads;
+use crate::entity::prelude::*;
+
+/// Longest message Discord accepts
+const MAX_MESSAGE_LENGTH: usize = 2000;
+/// Discord error code for a channel that was deleted
+const UNKNOWN_CHANNEL: isize = 10003;
+
+static OPEN_THREAD_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Split text into pieces short enough to send as separate messages
+fn split_message(text: &str) -> Vec<String> {
+    let mut pieces = vec![];
+    let mut current = String::new();
+    for c in text.chars() {
+        if current.len() + c.len_utf8() > MAX_MESSAGE_LENGTH {
+            pieces.push(std::mem::take(&mut current));
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+
+    pieces
+}
+
+/// Open a staff thread for a user's new conversation.
+/// Discord forum posts are always public threads, so conversations are kept private by the
+/// modmail forum only being visible to staff.
+async fn open_thread(ctx: &SerenityContext, data: &Data, forum: ChannelId, user: &User) -> anyhow::Result<ChannelId> {
+    let embed = CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .author(CreateEmbedAuthor::new(&user.name).icon_url(user.face()))
+        .description(format!("Modmail from <@{}> ({})\nReply with `/reply`, close with `/close`. Other messages here are not sent to the user.", user.id, user.id))
+        .field("Account created", format!("<t:{}:R>", user.id.created_at().unix_timestamp()), true)
+        .timestamp(Timestamp::now());
+    let thread = forum.create_forum_post(&ctx.http, CreateForumPost::new(
+        format!("{} ({})", user.name, user.id),
+        CreateMessage::new().embed(embed)
+    )).await?;
+
+    modmail_threads::ActiveModel {
+        user_id: Set(user.id.get() as i64),
+        thread_id: Set(thread.id.get() as i64),
+        opened_at: Set(Utc::now()),
+        ..Default::default()
+    }.insert(&data.db).await?;
+
+    Ok(thread.id)
+}
+
+/// Find the user's open staff thread, opening one if they don't have a conversation going.
+/// A `stale` thread that turned out to be deleted is closed first, so the conversation starts over.
+/// Returns the thread and whether it was just opened.
+async fn find_or_open_thread(
+    ctx: &SerenityContext,
+    data: &Data,
+    forum: ChannelId,
+    user: &User,
+    stale: Option<ChannelId>
+) -> anyhow::Result<(ChannelId, bool)> {
+    // Only one DM at a time gets to open a thread, so quick messages don't open one each
+    let _lock = OPEN_THREAD_LOCK.lock().await;
+
+    if let Some(stale) = stale {
+        ModmailThreads::update_many()
+            .col_expr(modmail_threads::Column::ClosedAt, sea_query::Expr::value(Utc::now()))
+            .filter(modmail_threads::Column::ThreadId.eq(stale.get() as i64))
+            .filter(modmail_threads::Column::ClosedAt.is_null())
+            .exec(&data.db).await?;
+    }
+
+    let open_thread_id = ModmailThreads::find()
+        .filter(modmail_threads::Column::UserId.eq(user.id.get() as i64))
+        .filter(modmail_threads::Column::ClosedAt.is_null())
+        .one(&data.db).await?
+        .map(|thread| ChannelId::new(thread.thread_id as u64));
+
+    Ok(match open_thread_id {
+        Some(thread_id) => (thread_id, false),
+        None => (open_thread(ctx, data, forum, user).await?, true)
+    })
+}
+
+/// Relay a DM to the user's staff thread, opening one if they don't have a conversation going
+pub async fn relay_dm(ctx: &SerenityContext, data: &Data, message: &Message) -> anyhow::Result<()> {
+    let Some(forum) = data.modmail_forum else {
+        return Ok(());
+    };
+
+    // Blocked users are ignored without letting them know
+    if ModmailBlocks::find_by_id(message.author.id.get() as i64).one(&data.db).await?.is_some() {
+        return Ok(());
+    }
+
+    let (mut thread_id, mut opened) = find_or_open_thread(ctx, data, forum, &message.author, None).await?;
+
+    let mut relayed = format!("**{}:** {}", message.author.name, message.content);
+    for attachment in &message.attachments {
+        relayed.push_str(&format!("\n{}", attachment.url));
+    }
+    let pieces = split_message(&relayed);
+
+    // Staff may have deleted the thread by hand, which ends the conversation
+    match thread_id.say(&ctx.http, &pieces[0]).await {
+        Ok(_) => {}
+        Err(serenity::Error::Http(HttpError::UnsuccessfulRequest(response))) if response.error.code == UNKNOWN_CHANNEL => {
+            (thread_id, opened) = find_or_open_thread(ctx, data, forum, &message.author, Some(thread_id)).await?;
+            thread_id.say(&ctx.http, &pieces[0]).await?;
+        }
+        Err(e) => return Err(e.into())
+    }
+    for piece in &pieces[1..] {
+        thread_id.say(&ctx.http, piece).await?;
+    }
+
+    if opened {
+        message.channel_id.say(&ctx.http, "Your message was sent to the moderators, they'll reply here. Any further messages you send will be passed on too").await?;
+    } else {
+        message.react(&ctx.http, ReactionType::Unicode("✅".to_string())).await?;
+    }
+
+    Ok(())
+}
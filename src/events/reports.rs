@@ -0,0 +1,315 @@
+use chrono::Utc;
+use serenity::all::{ButtonStyle, ChannelId, Color, ComponentInteraction, CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, CreateInputText, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateModal, EditInteractionResponse, HttpError, InputTextStyle, Member, Mentionable, MessageId, ModalInteraction, Permissions, StatusCode, Timestamp, UserId};
+use serenity::prelude::Context as SerenityContext;
+
+use luma1_data::sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, Set};
+
+use crate::commands::{self, DunceExpiry, Duration};
+use crate::context::{Data, P2SR_SERVER};
+use crate::entity::{report_reporters, reports};
+use crate::entity::reports::ReportStatus;
+use crate::entity::prelude::*;
+use super::{ephemeral_message, input_value};
+use super::router::custom_id;
+
+// Buttons and modals carry the report id
+pub const DELETE_PREFIX: &str = "report-delete";
+pub const DUNCE_PREFIX: &str = "report-dunce";
+pub const WARN_PREFIX: &str = "report-warn";
+pub const DISMISS_PREFIX: &str = "report-dismiss";
+pub const DUNCE_SUBMIT_PREFIX: &str = "report-dunce-submit";
+pub const WARN_SUBMIT_PREFIX: &str = "report-warn-submit";
+const DURATION_TEXT_ID: &str = "report-duration";
+const REASON_TEXT_ID: &str = "report-reason";
+
+/// Cut text down to fit an embed field or description
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars.saturating_sub(3)) {
+        Some((end, _)) if text.chars().count() > max_chars => format!("{}...", &text[..end]),
+        _ => text.to_string()
+    }
+}
+
+/// The alert moderators see for a report
+pub fn alert_embed(report: &reports::Model, reporters: &[report_reporters::Model]) -> CreateEmbed {
+    let mut embed = CreateEmbed::new()
+        .color(Color::from_rgb(179, 38, 255))
+        .title(if report.message_id.is_some() { "Message report" } else { "User report" })
+        .field("User", format!("<@{}> ({})", report.reported_user_id, report.reported_user_id), false)
+        .footer(CreateEmbedFooter::new(format!("Report #{}", report.id)))
+        .timestamp(Timestamp::from(report.created_at));
+
+    if let (Some(channel_id), Some(message_id)) = (report.channel_id, report.message_id) {
+        embed = embed
+            .description(match report.content.as_deref() {
+                Some(content) if !content.is_empty() => truncate(content, 4000),
+                _ => "*No text content*".to_string()
+            })
+            .field("Message", if report.message_deleted {
+                format!("Deleted from <#{}>", channel_id)
+            } else {
+                format!("https://discord.com/channels/{}/{}/{}", P2SR_SERVER, channel_id, message_id)
+            }, false);
+    }
+    if let Some(attachments) = &report.attachments {
+        embed = embed.field("Attachments", truncate(attachments, 1024), false);
+    }
+
+    let reporter_list = reporters.iter()
+        .map(|reporter| format!("<@{}>: {}", reporter.reporter_id,
+            reporter.reason.as_deref().unwrap_or("*No reason specified*")))
+        .collect::<Vec<_>>()
+        .join("\n");
+    embed = embed.field(format!("Reported by ({})", reporters.len()), truncate(&reporter_list, 1024), false);
+
+    embed.field("Status", match report.status {
+        ReportStatus::Open => "Open".to_string(),
+        _ => report.resolution.clone().unwrap_or_default()
+    }, false)
+}
+
+/// The action buttons on an open report
+pub fn alert_components(report: &reports::Model) -> Vec<CreateActionRow> {
+    if report.status != ReportStatus::Open {
+        return vec![];
+    }
+
+    let mut buttons = vec![];
+    if report.message_id.is_some() && !report.message_deleted {
+        buttons.push(CreateButton::new(custom_id(DELETE_PREFIX, &[&report.id])).label("Delete message").style(ButtonStyle::Danger));
+    }
+    buttons.push(CreateButton::new(custom_id(DUNCE_PREFIX, &[&report.id])).label("Dunce").style(ButtonStyle::Danger));
+    buttons.push(CreateButton::new(custom_id(WARN_PREFIX, &[&report.id])).label("Warn").style(ButtonStyle::Primary));
+    buttons.push(CreateButton::new(custom_id(DISMISS_PREFIX, &[&report.id])).label("Dismiss").style(ButtonStyle::Secondary));
+
+    vec![CreateActionRow::Buttons(buttons)]
+}
+
+/// Load a report along with everyone who reported it
+pub async fn load_report(data: &Data, report_id: i32) -> anyhow::Result<Option<(reports::Model, Vec<report_reporters::Model>)>> {
+    let Some(report) = Reports::find_by_id(report_id).one(&data.db).await? else {
+        return Ok(None);
+    };
+    let reporters = ReportReporters::find()
+        .filter(report_reporters::Column::ReportId.eq(report_id))
+        .order_by_asc(report_reporters::Column::ReportedAt)
+        .all(&data.db).await?;
+
+    Ok(Some((report, reporters)))
+}
+
+fn has_permission(member: Option<&Member>, permission: Permissions) -> bool {
+    member.and_then(|member| member.permissions).is_some_and(|permissions| permissions.contains(permission))
+}
+
+/// Mark a report as dealt with
+async fn resolve(data: &Data, report: reports::Model, status: ReportStatus, resolution: String, moderator_id: UserId) -> anyhow::Result<reports::Model> {
+    let mut record = report.into_active_model();
+    record.status = Set(status);
+    record.resolution = Set(Some(resolution));
+    record.resolved_by = Set(Some(moderator_id.get() as i64));
+    record.resolved_at = Set(Some(Utc::now()));
+
+    Ok(record.update(&data.db).await?)
+}
+
+fn update_alert(report: &reports::Model, reporters: &[report_reporters::Model]) -> CreateInteractionResponse {
+    CreateInteractionResponse::UpdateMessage(CreateInteractionResponseMessage::new()
+        .embed(alert_embed(report, reporters))
+        .components(alert_components(report)))
+}
+
+/// Update the alert after acknowledging the interaction
+fn edit_alert(report: &reports::Model, reporters: &[report_reporters::Model]) -> EditInteractionResponse {
+    EditInteractionResponse::new()
+        .embed(alert_embed(report, reporters))
+        .components(alert_components(report))
+}
+
+fn ephemeral_followup(content: impl Into<String>) -> CreateInteractionResponseFollowup {
+    CreateInteractionResponseFollowup::new().content(content).ephemeral(true)
+}
+
+pub async fn delete_message(ctx: &SerenityContext, data: &Data, component: &ComponentInteraction, report_id: i32) -> anyhow::Result<()> {
+    if !has_permission(component.member.as_ref(), Permissions::MANAGE_MESSAGES) {
+        component.create_response(&ctx.http, ephemeral_message("You need the Manage Messages permission to delete reported messages")).await?;
+        return Ok(());
+    }
+    // Deleting and logging can take longer than Discord waits for a response
+    component.create_response(&ctx.http, CreateInteractionResponse::Acknowledge).await?;
+
+    let Some((report, reporters)) = load_report(data, report_id).await? else {
+        return Ok(());
+    };
+    let (Some(channel_id), Some(message_id)) = (report.channel_id, report.message_id) else {
+        return Ok(());
+    };
+    let channel_id = ChannelId::new(channel_id as u64);
+
+    // A message someone else already deleted counts as deleted
+    match channel_id.delete_message(&ctx.http, MessageId::new(message_id as u64)).await {
+        Ok(()) => {}
+        Err(serenity::Error::Http(HttpError::UnsuccessfulRequest(response)))
+            if response.status_code == StatusCode::NOT_FOUND => {}
+        Err(e) => {
+            component.create_followup(&ctx.http, ephemeral_followup(
+                format!("```diff\n- {:#}\n```", anyhow::Error::new(e).context("Could not delete message"))
+            )).await?;
+            return Ok(());
+        }
+    }
+
+    let mut record = report.into_active_model();
+    record.message_deleted = Set(true);
+    let report = record.update(&data.db).await?;
+
+    component.edit_response(&ctx.http, edit_alert(&report, &reporters)).await?;
+
+    let log_result = commands::send_mod_action_log(&ctx.http, component.user.clone(), |embed| {
+        embed.description(format!("Deleted a message from <@{}> in {} reported in report #{}",
+            report.reported_user_id, channel_id.mention(), report.id))
+            .field("Content", truncate(report.content.as_deref().unwrap_or_default(), 1024), false)
+    }).await;
+    if let Err(e) = log_result {
+        component.create_followup(&ctx.http, ephemeral_followup(format!("```diff\n- {:#}\n```", e))).await?;
+    }
+
+    Ok(())
+}
+
+/// Ask the moderator how to dunce or warn the reported user
+pub async fn open_action(ctx: &SerenityContext, data: &Data, component: &ComponentInteraction, report_id: i32, dunce: bool) -> anyhow::Result<()> {
+    if !has_permission(component.member.as_ref(), Permissions::MODERATE_MEMBERS) {
+        component.create_response(&ctx.http, ephemeral_message("You need the Timeout Members permission to act on reports")).await?;
+        return Ok(());
+    }
+    let open = Reports::find_by_id(report_id).one(&data.db).await?
+        .is_some_and(|report| report.status == ReportStatus::Open);
+    if !open {
+        component.create_response(&ctx.http, ephemeral_message("This report was already dealt with")).await?;
+        return Ok(());
+    }
+
+    let reason = CreateActionRow::InputText(
+        CreateInputText::new(InputTextStyle::Paragraph, "Reason to record/DM", REASON_TEXT_ID)
+            .max_length(1000)
+            .required(!dunce)
+    );
+    let modal = match dunce {
+        true => CreateModal::new(custom_id(DUNCE_SUBMIT_PREFIX, &[&report_id]), "Dunce reported user")
+            .components(vec![
+                CreateActionRow::InputText(CreateInputText::new(InputTextStyle::Short, "How long to dunce", DURATION_TEXT_ID)
+                    .placeholder("1d12h")
+                    .max_length(32)),
+                reason
+            ]),
+        false => CreateModal::new(custom_id(WARN_SUBMIT_PREFIX, &[&report_id]), "Warn reported user")
+            .components(vec![reason])
+    };
+    component.create_response(&ctx.http, CreateInteractionResponse::Modal(modal)).await?;
+
+    Ok(())
+}
+
+/// Load an open report for a moderator acting on it from a modal, letting them know if it can't be acted on.
+/// The modal is acknowledged once the moderator is allowed to act, as acting can take longer than
+/// Discord waits for a response, so anything after this has to reply with followups.
+async fn open_report_for(ctx: &SerenityContext, data: &Data, modal: &ModalInteraction, report_id: i32) -> anyhow::Result<Option<(reports::Model, Vec<report_reporters::Model>)>> {
+    if !has_permission(modal.member.as_ref(), Permissions::MODERATE_MEMBERS) {
+        modal.create_response(&ctx.http, ephemeral_message("You need the Timeout Members permission to act on reports")).await?;
+        return Ok(None);
+    }
+    modal.create_response(&ctx.http, CreateInteractionResponse::Acknowledge).await?;
+
+    match load_report(data, report_id).await? {
+        Some((report, reporters)) if report.status == ReportStatus::Open => Ok(Some((report, reporters))),
+        _ => {
+            modal.create_followup(&ctx.http, ephemeral_followup("This report was already dealt with")).await?;
+            Ok(None)
+        }
+    }
+}
+
+pub async fn dunce_reported_user(ctx: &SerenityContext, data: &Data, modal: &ModalInteraction, report_id: i32) -> anyhow::Result<()> {
+    let Some((report, reporters)) = open_report_for(ctx, data, modal, report_id).await? else {
+        return Ok(());
+    };
+    let duration = match input_value(&modal.data.components, DURATION_TEXT_ID).unwrap_or_default().parse::<Duration>() {
+        Ok(duration) => duration,
+        Err(e) => {
+            modal.create_followup(&ctx.http, ephemeral_followup(format!("```diff\n- {}\n```", e))).await?;
+            return Ok(());
+        }
+    };
+    let reason = input_value(&modal.data.components, REASON_TEXT_ID);
+
+    let bot_id = ctx.cache.current_user().id;
+    let result = async {
+        let user = UserId::new(report.reported_user_id as u64).to_user(&ctx.http).await?;
+        commands::check_can_moderate(&ctx.http, bot_id, modal.user.id, &user).await?;
+        let plan = commands::plan_dunce(&ctx.http, &data.db, bot_id, user, DunceExpiry::FromNow(duration.clone()), reason).await?;
+        commands::execute_dunce(&ctx.http, &data.db, &modal.user, &plan).await
+    }.await;
+    let response = match result {
+        Ok(response) => response,
+        Err(e) => {
+            modal.create_followup(&ctx.http, ephemeral_followup(format!("```diff\n- {:#}\n```", e))).await?;
+            return Ok(());
+        }
+    };
+
+    let report = resolve(data, report, ReportStatus::Actioned,
+        format!("Dunced for {} by {}", duration, modal.user.mention()), modal.user.id).await?;
+    modal.edit_response(&ctx.http, edit_alert(&report, &reporters)).await?;
+    modal.create_followup(&ctx.http, ephemeral_followup(response)).await?;
+
+    Ok(())
+}
+
+pub async fn warn_reported_user(ctx: &SerenityContext, data: &Data, modal: &ModalInteraction, report_id: i32) -> anyhow::Result<()> {
+    let Some((report, reporters)) = open_report_for(ctx, data, modal, report_id).await? else {
+        return Ok(());
+    };
+    let reason = input_value(&modal.data.components, REASON_TEXT_ID).unwrap_or_default();
+
+    let bot_id = ctx.cache.current_user().id;
+    let result = async {
+        let user = UserId::new(report.reported_user_id as u64).to_user(&ctx.http).await?;
+        commands::check_can_moderate(&ctx.http, bot_id, modal.user.id, &user).await?;
+        commands::warn_user(&ctx.http, &data.db, &modal.user, &user, reason).await
+    }.await;
+    let response = match result {
+        Ok(response) => response,
+        Err(e) => {
+            modal.create_followup(&ctx.http, ephemeral_followup(format!("```diff\n- {:#}\n```", e))).await?;
+            return Ok(());
+        }
+    };
+
+    let report = resolve(data, report, ReportStatus::Actioned,
+        format!("Warned by {}", modal.user.mention()), modal.user.id).await?;
+    modal.edit_response(&ctx.http, edit_alert(&report, &reporters)).await?;
+    modal.create_followup(&ctx.http, ephemeral_followup(response)).await?;
+
+    Ok(())
+}
+
+pub async fn dismiss(ctx: &SerenityContext, data: &Data, component: &ComponentInteraction, report_id: i32) -> anyhow::Result<()> {
+    if !has_permission(component.member.as_ref(), Permissions::MODERATE_MEMBERS) {
+        component.create_response(&ctx.http, ephemeral_message("You need the Timeout Members permission to act on reports")).await?;
+        return Ok(());
+    }
+    let Some((report, reporters)) = load_report(data, report_id).await? else {
+        return Ok(());
+    };
+    if report.status != ReportStatus::Open {
+        component.create_response(&ctx.http, ephemeral_message("This report was already dealt with")).await?;
+        return Ok(());
+    }
+
+    let report = resolve(data, report, ReportStatus::Dismissed,
+        format!("Dismissed by {}", component.user.mention()), component.user.id).await?;
+    component.create_response(&ctx.http, update_alert(&report, &reporters)).await?;
+
+    Ok(())
+}
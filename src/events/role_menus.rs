@@ -0,0 +1,318 @@
+use std::collections::HashSet;
+
+use serenity::all::{ButtonStyle, Color, ComponentInteraction, ComponentInteractionDataKind, CreateActionRow, CreateAllowedMentions, CreateButton, CreateEmbed, CreateMessage, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, Member, Mentionable, Reaction, ReactionType, RoleId, UserId};
+use serenity::prelude::Context as SerenityContext;
+
+use luma1_data::entity::prelude::*;
+use luma1_data::sea_orm::{ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder};
+
+use crate::context::{Data, P2SR_SERVER};
+use crate::entity::{role_menu_entries, role_menus};
+use crate::entity::role_menus::RoleMenuStyle;
+use crate::entity::prelude::*;
+use super::ephemeral_message;
+use super::router::custom_id;
+
+pub const BUTTON_PREFIX: &str = "role-menu";
+pub const SELECT_PREFIX: &str = "role-menu-select";
+
+/// Most roles one menu can offer, as many as fit in a select menu or five rows of buttons
+pub const MAX_ENTRIES: usize = 25;
+
+/// Parse an emoji given by an admin, either unicode or a custom emoji like `<:name:id>`
+pub fn parse_emoji(emoji: &str) -> Option<ReactionType> {
+    ReactionType::try_from(emoji.trim()).ok()
+}
+
+/// Whether two emoji are the same, ignoring custom emoji names and variation selectors
+pub fn same_emoji(a: &ReactionType, b: &ReactionType) -> bool {
+    match (a, b) {
+        (ReactionType::Custom { id: a, .. }, ReactionType::Custom { id: b, .. }) => a == b,
+        (ReactionType::Unicode(a), ReactionType::Unicode(b)) =>
+            a.trim_end_matches('\u{fe0f}') == b.trim_end_matches('\u{fe0f}'),
+        _ => false
+    }
+}
+
+/// Load a role menu and the roles it offers, in the order they were added
+pub async fn load_menu(db: &impl ConnectionTrait, menu_id: i32) -> anyhow::Result<Option<(role_menus::Model, Vec<role_menu_entries::Model>)>> {
+    let Some(menu) = RoleMenus::find_by_id(menu_id).one(db).await? else {
+        return Ok(None);
+    };
+    let entries = RoleMenuEntries::find()
+        .filter(role_menu_entries::Column::MenuId.eq(menu_id))
+        .order_by_asc(role_menu_entries::Column::Position)
+        .all(db).await?;
+
+    Ok(Some((menu, entries)))
+}
+
+pub fn menu_embed(menu: &role_menus::Model, entries: &[role_menu_entries::Model]) -> CreateEmbed {
+    let mut lines: Vec<String> = menu.description.iter().cloned().collect();
+    if !lines.is_empty() {
+        lines.push(String::new());
+    }
+
+    if entries.is_empty() {
+        lines.push("*No roles yet*".to_string());
+    }
+    for entry in entries {
+        let mut line = match &entry.emoji {
+            Some(emoji) => format!("{} <@&{}>", emoji, entry.role_id),
+            None => format!("<@&{}>", entry.role_id)
+        };
+        if let Some(description) = &entry.description {
+            line.push_str(&format!(" - {}", description));
+        }
+        lines.push(line);
+    }
+
+    let mut rules = vec![];
+    if let Some(max_roles) = menu.max_roles {
+        rules.push(format!("Pick up to {}", max_roles));
+    }
+    if let Some(required_role_id) = menu.required_role_id {
+        rules.push(format!("Only for members with <@&{}>", required_role_id));
+    }
+    if menu.style == RoleMenuStyle::Reactions {
+        rules.push("React to take a role, remove your reaction to drop it".to_string());
+    }
+    if !rules.is_empty() {
+        lines.push(String::new());
+        lines.push(format!("*{}*", rules.join(" · ")));
+    }
+
+    CreateEmbed::new()
+        .color(Color::from_rgb(88, 101, 242))
+        .title(&menu.title)
+        .description(lines.join("\n"))
+}
+
+pub fn menu_components(menu: &role_menus::Model, entries: &[role_menu_entries::Model]) -> Vec<CreateActionRow> {
+    match menu.style {
+        RoleMenuStyle::Buttons => entries.chunks(5).map(|chunk| CreateActionRow::Buttons(chunk.iter().map(|entry| {
+            let button = CreateButton::new(custom_id(BUTTON_PREFIX, &[&menu.id, &entry.role_id]))
+                .label(&entry.label)
+                .style(ButtonStyle::Secondary);
+            match entry.emoji.as_deref().and_then(parse_emoji) {
+                Some(emoji) => button.emoji(emoji),
+                None => button
+            }
+        }).collect())).collect(),
+        RoleMenuStyle::Select if !entries.is_empty() => {
+            let options = entries.iter().map(|entry| {
+                let mut option = CreateSelectMenuOption::new(&entry.label, entry.role_id.to_string());
+                if let Some(emoji) = entry.emoji.as_deref().and_then(parse_emoji) {
+                    option = option.emoji(emoji);
+                }
+                if let Some(description) = &entry.description {
+                    option = option.description(description);
+                }
+                option
+            }).collect();
+            let max_values = menu.max_roles
+                .map(|max_roles| max_roles as usize)
+                .unwrap_or(entries.len())
+                .min(entries.len());
+
+            vec![CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(custom_id(SELECT_PREFIX, &[&menu.id]), CreateSelectMenuKind::String { options })
+                    .placeholder("Pick your roles")
+                    .min_values(0)
+                    .max_values(max_values as u8)
+            )]
+        }
+        _ => vec![]
+    }
+}
+
+/// Roles offered by a menu that a member already has
+fn held_roles(member: &Member, entries: &[role_menu_entries::Model]) -> HashSet<RoleId> {
+    entries.iter()
+        .map(|entry| RoleId::new(entry.role_id as u64))
+        .filter(|role_id| member.roles.contains(role_id))
+        .collect()
+}
+
+/// Why a member isn't allowed to end up holding `holding` roles from a menu, if they aren't
+async fn refuse_reason(data: &Data, menu: &role_menus::Model, member: &Member, holding: usize) -> anyhow::Result<Option<String>> {
+    // The dunce role can't be told apart from other roles, so go by the dunce record
+    if DunceInstants::find_by_id(member.user.id.get() as i64).one(&data.db).await?.is_some() {
+        return Ok(Some("You can't pick roles while dunced".to_string()));
+    }
+    if let Some(required_role_id) = menu.required_role_id
+        && !member.roles.contains(&RoleId::new(required_role_id as u64)) {
+        return Ok(Some(format!("You need the <@&{}> role to use this menu", required_role_id)));
+    }
+    if let Some(max_roles) = menu.max_roles
+        && holding > max_roles as usize {
+        return Ok(Some(format!("You can only have {} roles from this menu, drop one first", max_roles)));
+    }
+
+    Ok(None)
+}
+
+/// Note a role change in the role log. The change was already made, so failing to log it only gets reported.
+async fn log_change(
+    ctx: &SerenityContext,
+    data: &Data,
+    menu: &role_menus::Model,
+    user_id: UserId,
+    added: &[RoleId],
+    removed: &[RoleId]
+) {
+    let Some(role_log_channel) = data.role_log_channel else {
+        return;
+    };
+
+    let changes: Vec<String> = added.iter().map(|role_id| format!("+{}", role_id.mention()))
+        .chain(removed.iter().map(|role_id| format!("-{}", role_id.mention())))
+        .collect();
+    let result = role_log_channel.send_message(&ctx.http, CreateMessage::new()
+        .content(format!("{} ({}) {} using role menu `#{}` **{}**",
+            user_id.mention(), user_id, changes.join(" "), menu.id, menu.title))
+        .allowed_mentions(CreateAllowedMentions::new())
+    ).await;
+    if let Err(e) = result {
+        eprintln!("Failed to log role menu change for {}: {:?}", user_id, e);
+    }
+}
+
+pub async fn toggle_role(ctx: &SerenityContext, data: &Data, component: &ComponentInteraction, menu_id: i32, role_id: RoleId) -> anyhow::Result<()> {
+    let Some(member) = &component.member else {
+        return Ok(());
+    };
+    let Some((menu, entries)) = load_menu(&data.db, menu_id).await? else {
+        component.create_response(&ctx.http, ephemeral_message("This role menu no longer exists")).await?;
+        return Ok(());
+    };
+    if !entries.iter().any(|entry| entry.role_id == role_id.get() as i64) {
+        component.create_response(&ctx.http, ephemeral_message("That role is no longer offered by this menu")).await?;
+        return Ok(());
+    }
+
+    let response = if member.roles.contains(&role_id) {
+        member.remove_role(&ctx.http, role_id).await?;
+        log_change(ctx, data, &menu, member.user.id, &[], &[role_id]).await;
+        format!("Removed {}", role_id.mention())
+    } else {
+        let holding = held_roles(member, &entries).len() + 1;
+        if let Some(reason) = refuse_reason(data, &menu, member, holding).await? {
+            component.create_response(&ctx.http, ephemeral_message(format!("```diff\n- {}\n```", reason))).await?;
+            return Ok(());
+        }
+        member.add_role(&ctx.http, role_id).await?;
+        log_change(ctx, data, &menu, member.user.id, &[role_id], &[]).await;
+        format!("Gave you {}", role_id.mention())
+    };
+
+    component.create_response(&ctx.http, ephemeral_message(response)).await?;
+
+    Ok(())
+}
+
+pub async fn select_roles(ctx: &SerenityContext, data: &Data, component: &ComponentInteraction, menu_id: i32) -> anyhow::Result<()> {
+    let Some(member) = &component.member else {
+        return Ok(());
+    };
+    let ComponentInteractionDataKind::StringSelect { values } = &component.data.kind else {
+        return Ok(());
+    };
+    let Some((menu, entries)) = load_menu(&data.db, menu_id).await? else {
+        component.create_response(&ctx.http, ephemeral_message("This role menu no longer exists")).await?;
+        return Ok(());
+    };
+
+    // Only roles still on offer can be picked, whatever the message says
+    let offered: HashSet<RoleId> = entries.iter().map(|entry| RoleId::new(entry.role_id as u64)).collect();
+    let selected: HashSet<RoleId> = values.iter()
+        .filter_map(|value| value.parse::<RoleId>().ok())
+        .filter(|role_id| offered.contains(role_id))
+        .collect();
+    let held = held_roles(member, &entries);
+    let added: Vec<RoleId> = selected.difference(&held).copied().collect();
+    let removed: Vec<RoleId> = held.difference(&selected).copied().collect();
+
+    if !added.is_empty()
+        && let Some(reason) = refuse_reason(data, &menu, member, selected.len()).await? {
+        component.create_response(&ctx.http, ephemeral_message(format!("```diff\n- {}\n```", reason))).await?;
+        return Ok(());
+    }
+    if added.is_empty() && removed.is_empty() {
+        component.create_response(&ctx.http, ephemeral_message("Your roles are unchanged")).await?;
+        return Ok(());
+    }
+
+    if !removed.is_empty() {
+        member.remove_roles(&ctx.http, &removed).await?;
+    }
+    if !added.is_empty() {
+        member.add_roles(&ctx.http, &added).await?;
+    }
+    log_change(ctx, data, &menu, member.user.id, &added, &removed).await;
+
+    let mut response = vec![];
+    if !added.is_empty() {
+        response.push(format!("Gave you {}", added.iter().map(|role_id| role_id.mention().to_string()).collect::<Vec<_>>().join(", ")));
+    }
+    if !removed.is_empty() {
+        response.push(format!("Removed {}", removed.iter().map(|role_id| role_id.mention().to_string()).collect::<Vec<_>>().join(", ")));
+    }
+    component.create_response(&ctx.http, ephemeral_message(response.join("\n"))).await?;
+
+    Ok(())
+}
+
+/// Give or take a role when someone reacts to a reaction role menu
+pub async fn handle_reaction(ctx: &SerenityContext, data: &Data, reaction: &Reaction, added: bool) -> anyhow::Result<()> {
+    let Some(user_id) = reaction.user_id else {
+        return Ok(());
+    };
+    // The bot's own reactions are the ones members click on
+    if reaction.guild_id != Some(P2SR_SERVER) || user_id == ctx.cache.current_user().id {
+        return Ok(());
+    }
+
+    let Some(menu) = RoleMenus::find()
+        .filter(role_menus::Column::MessageId.eq(reaction.message_id.get() as i64))
+        .filter(role_menus::Column::Style.eq(RoleMenuStyle::Reactions))
+        .one(&data.db).await? else {
+        return Ok(());
+    };
+    let Some((menu, entries)) = load_menu(&data.db, menu.id).await? else {
+        return Ok(());
+    };
+    let Some(entry) = entries.iter().find(|entry| entry.emoji.as_deref()
+        .and_then(parse_emoji)
+        .is_some_and(|emoji| same_emoji(&emoji, &reaction.emoji))) else {
+        return Ok(());
+    };
+    let role_id = RoleId::new(entry.role_id as u64);
+    let member = P2SR_SERVER.member(&ctx.http, user_id).await?;
+
+    if !added {
+        if member.roles.contains(&role_id) {
+            member.remove_role(&ctx.http, role_id).await?;
+            log_change(ctx, data, &menu, user_id, &[], &[role_id]).await;
+        }
+        return Ok(());
+    }
+    if member.roles.contains(&role_id) {
+        return Ok(());
+    }
+
+    let holding = held_roles(&member, &entries).len() + 1;
+    if let Some(reason) = refuse_reason(data, &menu, &member, holding).await? {
+        // Take the reaction back so it doesn't look like the role was given
+        reaction.delete(ctx).await?;
+        if let Ok(channel) = user_id.create_dm_channel(&ctx.http).await {
+            let _ = channel.say(&ctx.http, format!("Couldn't give you a role from **{}**: {}", menu.title, reason)).await;
+        }
+        return Ok(());
+    }
+
+    member.add_role(&ctx.http, role_id).await?;
+    log_change(ctx, data, &menu, user_id, &[role_id], &[]).await;
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! Sends button, select menu and modal interactions to their handlers by `custom_id`.
+//!
+//! Ids look like `l3ma:v1:<route>:<args>`. Everything a handler needs is in the id or the
+//! database, so components keep working after the bot restarts. Ids from before versioning
+//! (`<route>:<args>`) are routed the same way, so buttons on old messages keep working too.
+//! Ids that don't match a route are left alone, as they belong to in-process collectors like
+//! confirmation prompts and pagination.
+//!
+//! Routes are registered next to the commands that post their components: each command module
+//! exports a `routes()` table and [`crate::commands::generate_routes`] collects them, the same way
+//! `generate_commands` collects the commands. The handlers live under `events` with the builders
+//! for their components (`appeal_button`, `alert_components`, `menu_components`), so the ids are
+//! built and parsed in one place.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use serenity::all::{ComponentInteraction, Interaction, ModalInteraction};
+use serenity::prelude::Context as SerenityContext;
+
+use crate::context::Data;
+use crate::commands;
+use super::ephemeral_message;
+
+const NAMESPACE: &str = "l3ma";
+/// Bump when existing ids change meaning, keeping a route around that understands the old ones
+const VERSION: &str = "v1";
+
+const STALE_MESSAGE: &str = "```diff\n- This is out of date or isn't valid anymore\n```";
+
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'a>>;
+
+pub enum RouteHandler {
+    Component(for<'a> fn(&'a SerenityContext, &'a Data, &'a ComponentInteraction, Args<'a>) -> HandlerFuture<'a>),
+    Modal(for<'a> fn(&'a SerenityContext, &'a Data, &'a ModalInteraction, Args<'a>) -> HandlerFuture<'a>),
+}
+
+pub struct Route {
+    /// Also the prefix of the route's ids from before versioning
+    pub name: &'static str,
+    pub handler: RouteHandler,
+}
+
+static ROUTES: LazyLock<Vec<Route>> = LazyLock::new(commands::generate_routes);
+
+/// Build the `custom_id` of a component or modal handled by `route`
+pub fn custom_id(route: &str, args: &[&dyn fmt::Display]) -> String {
+    let mut custom_id = format!("{}:{}:{}", NAMESPACE, VERSION, route);
+    for arg in args {
+        custom_id.push_str(&format!(":{}", arg));
+    }
+
+    custom_id
+}
+
+/// A `custom_id` that names a route but can't be handled, because it was forged or has gone stale
+#[derive(Debug)]
+pub struct InvalidCustomId;
+
+impl fmt::Display for InvalidCustomId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid custom id")
+    }
+}
+
+impl std::error::Error for InvalidCustomId {}
+
+/// The values after the route name in a `custom_id`
+pub struct Args<'a>(Vec<&'a str>);
+
+impl Args<'_> {
+    /// Parse an argument. Discord ids parse into their non-zero id types, so a forged `0` is invalid
+    /// rather than a panic in `Id::new`.
+    pub fn get<T: FromStr>(&self, index: usize) -> Result<T, InvalidCustomId> {
+        self.0.get(index)
+            .and_then(|arg| arg.parse().ok())
+            .ok_or(InvalidCustomId)
+    }
+}
+
+/// Split a `custom_id` into its route and arguments.
+/// `Err` means the id is in our namespace but doesn't name a route this version of the bot has.
+fn parse(custom_id: &str) -> Result<Option<(&'static Route, Args<'_>)>, InvalidCustomId> {
+    let mut parts = custom_id.split(':');
+    let namespaced = parts.next() == Some(NAMESPACE);
+    let name = match namespaced {
+        true => {
+            if parts.next() != Some(VERSION) {
+                return Err(InvalidCustomId);
+            }
+            parts.next().ok_or(InvalidCustomId)?
+        }
+        false => {
+            parts = custom_id.split(':');
+            parts.next().unwrap_or_default()
+        }
+    };
+
+    match ROUTES.iter().find(|route| route.name == name) {
+        Some(route) => Ok(Some((route, Args(parts.collect())))),
+        None if namespaced => Err(InvalidCustomId),
+        None => Ok(None)
+    }
+}
+
+/// Hand a component or modal interaction to the route its `custom_id` names
+pub async fn dispatch(ctx: &SerenityContext, data: &Data, interaction: &Interaction) -> anyhow::Result<()> {
+    let result = match interaction {
+        Interaction::Component(component) => match parse(&component.data.custom_id) {
+            Ok(Some((Route { handler: RouteHandler::Component(handler), .. }, args))) => handler(ctx, data, component, args).await,
+            Ok(Some(_)) => Err(InvalidCustomId.into()),
+            Ok(None) => return Ok(()),
+            Err(e) => Err(e.into())
+        },
+        Interaction::Modal(modal) => match parse(&modal.data.custom_id) {
+            Ok(Some((Route { handler: RouteHandler::Modal(handler), .. }, args))) => handler(ctx, data, modal, args).await,
+            Ok(Some(_)) => Err(InvalidCustomId.into()),
+            Ok(None) => return Ok(()),
+            Err(e) => Err(e.into())
+        },
+        _ => return Ok(())
+    };
+
+    match result {
+        Err(e) if e.is::<InvalidCustomId>() => {
+            let response = ephemeral_message(STALE_MESSAGE);
+            match interaction {
+                Interaction::Component(component) => component.create_response(&ctx.http, response).await?,
+                Interaction::Modal(modal) => modal.create_response(&ctx.http, response).await?,
+                _ => {}
+            }
+            Ok(())
+        }
+        result => result
+    }
+}
@@ -1,8 +1,8 @@
-#![feature(macro_metavar_expr)]
-
 mod commands;
 pub mod context;
+mod entity;
 mod events;
+mod scheduler;
 
 use std::collections::HashSet;
 use poise::serenity_prelude as serenity;
@@ -37,9 +37,11 @@ async fn main() -> anyhow::Result<()> {
             owners: HashSet::from([152559951930327040.into()]),
             ..Default::default()
         })
-        .setup(|_ctx, _ready, _framework| {
+        .setup(|ctx, _ready, _framework| {
             Box::pin(async move {
-                Ok(Data::new().await.expect("Failed to create state data"))
+                let data = Data::new().await.expect("Failed to create state data");
+                scheduler::spawn(ctx.clone(), data.db.clone());
+                Ok(data)
             })
         })
         .build();
@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+use luma1_data::sea_orm::DatabaseConnection;
+use serenity::prelude::Context as SerenityContext;
+
+use crate::commands;
+
+/// How often to check for scheduled work that has come due
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Start the background loop that runs scheduled actions stored in the database.
+/// Everything it does is persisted, so work scheduled before a restart is picked up afterwards.
+pub fn spawn(ctx: SerenityContext, db: DatabaseConnection) {
+    tokio::spawn(async move {
+        // Fix anything that drifted while the bot was offline before regular work starts
+        let bot_user = ctx.cache.current_user().clone().into();
+        if let Err(e) = commands::reconcile_dunces_on_startup(&ctx.http, &db, bot_user).await {
+            eprintln!("Failed to reconcile dunces on startup: {:?}", e);
+        }
+
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = commands::revert_expired_slowmodes(&ctx, &db).await {
+                eprintln!("Failed to revert expired slowmodes: {:?}", e);
+            }
+            if let Err(e) = commands::deliver_due_reminders(&ctx, &db).await {
+                eprintln!("Failed to deliver reminders: {:?}", e);
+            }
+        }
+    });
+}